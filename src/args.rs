@@ -7,6 +7,8 @@ pub struct Args {
     pub mode: SorgMode,
     pub path: PathBuf,
     pub verbose: bool,
+    /// whether source blocks can be executed while building, set with `--allow-exec`
+    pub allow_exec: bool,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Default)]
@@ -51,6 +53,7 @@ impl Args {
         let (args, argv) = argmap::parse(args);
 
         let verbose = argv.contains_key("v") || argv.contains_key("verbose");
+        let allow_exec = argv.contains_key("allow-exec");
 
         let (mode, path) = match &args[..] {
             [] => (SorgMode::Run, PathBuf::from("./blog.org")),
@@ -66,6 +69,7 @@ impl Args {
             mode,
             path,
             verbose,
+            allow_exec,
         })
     }
 
@@ -111,6 +115,7 @@ mod tests {
                 mode: SorgMode::Run,
                 path: PathBuf::from("./blog.org"),
                 verbose: false,
+                allow_exec: false,
             },
         );
         test!(
@@ -119,6 +124,7 @@ mod tests {
                 mode: SorgMode::Watch,
                 path: PathBuf::from("hey.org"),
                 verbose: true,
+                allow_exec: false,
             },
         );
         test!(
@@ -129,6 +135,7 @@ mod tests {
                 },
                 path: PathBuf::from("./blog.org"),
                 verbose: false,
+                allow_exec: false,
             },
         );
         test!(
@@ -139,6 +146,16 @@ mod tests {
                 },
                 path: PathBuf::from("hey.org"),
                 verbose: false,
+                allow_exec: false,
+            },
+        );
+        test!(
+            ["run", "hey.org", "--allow-exec"],
+            Args {
+                mode: SorgMode::Run,
+                path: PathBuf::from("hey.org"),
+                verbose: false,
+                allow_exec: true,
             },
         );
 
@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+
+use color_eyre::{eyre::bail, Result};
+use unicode_normalization::UnicodeNormalization;
+
+/// an entry of a bibtex file, like an `@article`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub kind: String,
+    pub fields: HashMap<String, String>,
+}
+
+impl Entry {
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).map(String::as_str)
+    }
+
+    /// the authors, or editors if there are none, as they were written
+    pub fn names(&self) -> Vec<&str> {
+        self.get("author")
+            .or_else(|| self.get("editor"))
+            .map(|names| names.split(" and ").map(str::trim).collect())
+            .unwrap_or_default()
+    }
+
+    /// last names of the authors, from both "Last, First" and "First Last"
+    pub fn last_names(&self) -> Vec<&str> {
+        self.names()
+            .into_iter()
+            .map(|name| match name.split_once(',') {
+                Some((last, _)) => last.trim(),
+                None => name.rsplit(' ').next().unwrap_or(name),
+            })
+            .collect()
+    }
+
+    pub fn year(&self) -> Option<&str> {
+        self.get("year")
+            .or_else(|| self.get("date").and_then(|date| date.get(..4)))
+    }
+}
+
+/// the entries of a bibtex file
+#[derive(Debug, Default, Clone)]
+pub struct Bibliography {
+    entries: HashMap<String, Entry>,
+}
+
+impl Bibliography {
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.get(key)
+    }
+
+    /// parses a bibtex file
+    ///
+    /// `@string` abbreviations and `#` concatenation are supported, `@comment` and `@preamble` are ignored,
+    /// and so is text between entries.
+    /// braces and latex commands are removed from values, and accents like `\'e` become the characters they make
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            source,
+            pos: 0,
+            strings: HashMap::new(),
+        };
+        let mut entries = HashMap::new();
+
+        while let Some(at) = parser.rest().find('@') {
+            parser.pos += at + 1;
+            let kind = parser.identifier().to_lowercase();
+            parser.skip_whitespace();
+            // anything else is text between entries, like an email address
+            let close = match parser.peek() {
+                Some('{') if !kind.is_empty() => '}',
+                Some('(') if !kind.is_empty() => ')',
+                _ => continue,
+            };
+            parser.next();
+
+            match kind.as_str() {
+                "comment" | "preamble" => parser.skip_group(close)?,
+                "string" => {
+                    let (name, value) = parser.field()?;
+                    parser.strings.insert(name, value);
+                    parser.skip_group(close)?;
+                }
+                _ => {
+                    let key = parser.until(&[',', close]).trim().to_string();
+                    let mut fields = HashMap::new();
+                    loop {
+                        parser.skip_whitespace();
+                        match parser.peek() {
+                            Some(',') => {
+                                parser.next();
+                            }
+                            Some(c) if c == close => {
+                                parser.next();
+                                break;
+                            }
+                            Some(_) => {
+                                let (name, value) = parser.field()?;
+                                fields.insert(name, value);
+                            }
+                            None => bail!("unclosed bibliography entry '{key}'"),
+                        }
+                    }
+                    entries.insert(key.clone(), Entry { key, kind, fields });
+                }
+            }
+        }
+
+        Ok(Self { entries })
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    strings: HashMap<String, String>,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn until(&mut self, ends: &[char]) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(ends).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn identifier(&mut self) -> &'a str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || "_-:.+/".contains(c)))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// skips until the end of the current group, `close` included
+    fn skip_group(&mut self, close: char) -> Result<()> {
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => return Ok(()),
+                _ => {}
+            }
+        }
+        bail!("unclosed group in bibliography")
+    }
+
+    /// parses `name = value # value`
+    fn field(&mut self) -> Result<(String, String)> {
+        self.skip_whitespace();
+        let name = self.identifier().to_lowercase();
+        self.skip_whitespace();
+        if self.next() != Some('=') {
+            bail!("expected '=' after field '{name}' in bibliography");
+        }
+
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.next();
+                    value.push_str(&self.braced()?);
+                }
+                Some('"') => {
+                    self.next();
+                    value.push_str(&self.quoted()?);
+                }
+                Some(c) if c.is_alphanumeric() => {
+                    let word = self.identifier();
+                    match self.strings.get(&word.to_lowercase()) {
+                        Some(string) => value.push_str(string),
+                        None => value.push_str(word),
+                    }
+                }
+                _ => bail!("expected a value for field '{name}' in bibliography"),
+            }
+            self.skip_whitespace();
+            if self.peek() == Some('#') {
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        Ok((name, clean_value(&value)))
+    }
+
+    /// contents of a `{...}` value, with the opening brace already consumed
+    fn braced(&mut self) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => {
+                    self.next();
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(self.source[start..self.pos - 1].to_string()),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        bail!("unclosed '{{' in bibliography")
+    }
+
+    /// contents of a `"..."` value, with the opening quote already consumed
+    fn quoted(&mut self) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => {
+                    self.next();
+                }
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '"' if depth == 0 => return Ok(self.source[start..self.pos - 1].to_string()),
+                _ => {}
+            }
+        }
+        bail!("unclosed '\"' in bibliography")
+    }
+}
+
+/// removes braces and latex commands, turns accents into the characters they make, and collapses whitespace
+fn clean_value(value: &str) -> String {
+    let value = value.replace("---", "—").replace("--", "–");
+    let chars = value.chars().collect::<Vec<_>>();
+
+    let mut out = String::new();
+    for c in latex_text(&chars).chars() {
+        if !c.is_whitespace() {
+            out.push(c);
+        } else if !out.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+    out.trim().nfc().collect()
+}
+
+/// the text of some latex, with accents as combining characters
+///
+/// commands that aren't accents or letters, like `\emph`, are dropped, and their arguments kept
+fn latex_text(chars: &[char]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+
+    while let Some(&c) = chars.get(i) {
+        i += 1;
+        match c {
+            '{' | '}' => {}
+            '~' => out.push('\u{a0}'),
+            '\\' => {
+                let Some(&next) = chars.get(i) else {
+                    break;
+                };
+                let name = if next.is_ascii_alphabetic() {
+                    let name = chars[i..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_alphabetic())
+                        .collect::<String>();
+                    i += name.len();
+                    // spaces after a command name only end it
+                    while chars.get(i) == Some(&' ') {
+                        i += 1;
+                    }
+                    name
+                } else {
+                    i += 1;
+                    next.to_string()
+                };
+
+                if let Some(mark) = accent(&name) {
+                    let (argument, len) = accent_argument(&chars[i..]);
+                    i += len;
+                    let argument = latex_text(argument);
+                    let mut argument = argument.chars();
+                    out.extend(argument.next());
+                    out.push(mark);
+                    out.extend(argument);
+                } else if let Some(letter) = letter(&name) {
+                    out.push_str(letter);
+                } else if !next.is_ascii_alphabetic() {
+                    // escaped characters, like `\&`
+                    out.push(next);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// what an accent applies to, either a group like `{o}`, a command like `\i` or a single character
+///
+/// returns it and how many characters it takes
+fn accent_argument(chars: &[char]) -> (&[char], usize) {
+    match chars.first() {
+        Some('{') => {
+            let mut depth = 0;
+            for (i, c) in chars.iter().enumerate() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 1 => return (&chars[1..i], i + 1),
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            (&chars[1..], chars.len())
+        }
+        Some('\\') => {
+            let len = 1 + chars[1..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .count()
+                .max(1);
+            let len = len.min(chars.len());
+            let spaces = chars[len..].iter().take_while(|c| **c == ' ').count();
+            (&chars[..len], len + spaces)
+        }
+        Some(_) => (&chars[..1], 1),
+        None => (chars, 0),
+    }
+}
+
+/// the combining character for a latex accent, like `\'` or `\H`
+fn accent(command: &str) -> Option<char> {
+    Some(match command {
+        "'" => '\u{301}',
+        "`" => '\u{300}',
+        "^" => '\u{302}',
+        "\"" => '\u{308}',
+        "~" => '\u{303}',
+        "=" => '\u{304}',
+        "." => '\u{307}',
+        "u" => '\u{306}',
+        "v" => '\u{30c}',
+        "H" => '\u{30b}',
+        "r" => '\u{30a}',
+        "c" => '\u{327}',
+        "k" => '\u{328}',
+        "d" => '\u{323}',
+        "b" => '\u{331}',
+        _ => return None,
+    })
+}
+
+/// letters written as latex commands, like `\ss`
+fn letter(command: &str) -> Option<&'static str> {
+    Some(match command {
+        "ss" => "ß",
+        "o" => "ø",
+        "O" => "Ø",
+        "l" => "ł",
+        "L" => "Ł",
+        "ae" => "æ",
+        "AE" => "Æ",
+        "oe" => "œ",
+        "OE" => "Œ",
+        "aa" => "å",
+        "AA" => "Å",
+        // dotless, so accents can go on top of them
+        "i" => "i",
+        "j" => "j",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bibtex() -> Result<()> {
+        let source = r#"
+@string{ acm = "Communications of the {ACM}" }
+@comment{ this is ignored }
+
+@article{knuth1984,
+  author = {Knuth, Donald E.},
+  title = {Literate {Programming}},
+  journal = acm # " (special issue)",
+  year = 1984,
+  pages = {97--111},
+}
+
+@book(sicp,
+  author = "Harold Abelson and Gerald Jay Sussman",
+  title = "Structure and Interpretation of Computer Programs",
+  date = {1996-07-25}
+)
+"#;
+
+        let bibliography = Bibliography::parse(source)?;
+
+        let knuth = bibliography.get("knuth1984").unwrap();
+        assert_eq!("article", knuth.kind);
+        assert_eq!(Some("Literate Programming"), knuth.get("title"));
+        assert_eq!(
+            Some("Communications of the ACM (special issue)"),
+            knuth.get("journal")
+        );
+        assert_eq!(Some("97–111"), knuth.get("pages"));
+        assert_eq!(Some("1984"), knuth.year());
+        assert_eq!(vec!["Knuth"], knuth.last_names());
+
+        let sicp = bibliography.get("sicp").unwrap();
+        assert_eq!(vec!["Abelson", "Sussman"], sicp.last_names());
+        assert_eq!(Some("1996"), sicp.year());
+
+        assert!(Bibliography::parse("@book{unclosed, title = {oops").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_text_between_entries() -> Result<()> {
+        let source = r#"
+questions to someone@example.com, or @ me
+
+@misc{erdos,
+  author = {Paul Erd\H{o}s and Ren\'{e} Descartes and Kurt G{\"o}del},
+  title = {\emph{Na\"\i ve} \c{c}a and \v{S}koda, {\ss} \& \AA{}ngstr\"om},
+}
+"#;
+
+        let bibliography = Bibliography::parse(source)?;
+        let erdos = bibliography.get("erdos").unwrap();
+        assert_eq!(
+            Some("Paul Erdős and René Descartes and Kurt Gödel"),
+            erdos.get("author")
+        );
+        assert_eq!(Some("Naïve ça and Škoda, ß & Ångström"), erdos.get("title"));
+
+        Ok(())
+    }
+}
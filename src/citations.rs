@@ -0,0 +1,485 @@
+use color_eyre::{eyre::bail, Result};
+use orgize::{elements::FnRef, export::HtmlEscape, indextree::NodeId, Element, Headline, Org};
+use serde_derive::Serialize;
+
+use crate::{
+    bibliography::{Bibliography, Entry},
+    footnotes::referenced_definitions,
+};
+
+/// how citations are written, set with `#+citation_style:`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CitationStyle {
+    /// `(Knuth 1984)`, with the bibliography sorted by author
+    #[default]
+    AuthorYear,
+    /// `[1]`, with the bibliography in the order entries are first cited
+    Numeric,
+}
+
+impl CitationStyle {
+    pub fn parse(style: &str) -> Result<Self> {
+        match style {
+            "author-year" => Ok(Self::AuthorYear),
+            "numeric" => Ok(Self::Numeric),
+            _ => bail!("unknown citation style '{style}', use 'author-year' or 'numeric'"),
+        }
+    }
+}
+
+/// a citation like `[cite:see @knuth1984 p. 3; @sicp]`
+#[derive(Debug, PartialEq)]
+pub struct Citation<'a> {
+    /// the style after the slash, like `t` in `[cite/t:@key]`
+    pub style: Option<&'a str>,
+    pub prefix: &'a str,
+    pub suffix: &'a str,
+    pub references: Vec<Reference<'a>>,
+}
+
+/// a single `@key` in a citation, with the text around it
+#[derive(Debug, PartialEq)]
+pub struct Reference<'a> {
+    pub prefix: &'a str,
+    pub key: &'a str,
+    pub suffix: &'a str,
+}
+
+impl Citation<'_> {
+    /// textual citations are part of the sentence, like `Knuth (1984)`
+    fn is_textual(&self) -> bool {
+        matches!(self.style, Some("t" | "text"))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Part<'a> {
+    Text(&'a str),
+    Citation(Citation<'a>),
+}
+
+/// splits text into plain text and org-cite citations
+///
+/// orgize doesn't parse citations, so they arrive as part of the text
+pub fn split_citations(text: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    // start of the text that hasn't been added to `parts` yet
+    let mut start = 0;
+    let mut search = 0;
+
+    while let Some(found) = text[search..].find("[cite") {
+        let open = search + found;
+        let citation = text[open..].find(']').and_then(|len| {
+            let citation = parse_citation(&text[open + 1..open + len])?;
+            Some((citation, open + len + 1))
+        });
+
+        match citation {
+            Some((citation, end)) => {
+                if open > start {
+                    parts.push(Part::Text(&text[start..open]));
+                }
+                parts.push(Part::Citation(citation));
+                start = end;
+                search = end;
+            }
+            None => search = open + 1,
+        }
+    }
+    if start < text.len() {
+        parts.push(Part::Text(&text[start..]));
+    }
+
+    parts
+}
+
+/// parses the inside of the brackets, like `cite/t:@key`
+fn parse_citation(inner: &str) -> Option<Citation<'_>> {
+    let (head, body) = inner.split_once(':')?;
+    let style = match head.strip_prefix("cite")? {
+        "" => None,
+        style => Some(style.strip_prefix('/')?),
+    };
+
+    let mut citation = Citation {
+        style,
+        prefix: "",
+        suffix: "",
+        references: Vec::new(),
+    };
+
+    let segments = body.split(';').collect::<Vec<_>>();
+    for (i, segment) in segments.iter().enumerate() {
+        match parse_reference(segment) {
+            Some(reference) => citation.references.push(reference),
+            // text without a key is a prefix or suffix for the whole citation
+            None if i == 0 => citation.prefix = segment.trim(),
+            None if i == segments.len() - 1 => citation.suffix = segment.trim(),
+            None => return None,
+        }
+    }
+
+    (!citation.references.is_empty()).then_some(citation)
+}
+
+fn parse_reference(segment: &str) -> Option<Reference<'_>> {
+    let at = segment.find('@')?;
+    let after = &segment[at + 1..];
+    let len = after
+        .find(|c: char| !(c.is_alphanumeric() || "-_:.#$%&+?<>~/".contains(c)))
+        .unwrap_or(after.len());
+    // keys can't end with punctuation, so `@key.` doesn't take the period
+    let key = after[..len].trim_end_matches(|c: char| !c.is_alphanumeric());
+    if key.is_empty() {
+        return None;
+    }
+
+    Some(Reference {
+        prefix: segment[..at].trim(),
+        key,
+        suffix: after[key.len()..].trim(),
+    })
+}
+
+/// an entry of the bibliography of a page
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BibliographyItem {
+    pub key: String,
+    /// position of the first citation of this entry
+    pub number: usize,
+    /// the authors, like `Knuth` or `Abelson and Sussman`
+    pub authors: String,
+    pub year: String,
+    /// the full reference, rendered to html
+    pub html: String,
+}
+
+/// id of the bibliography entry for a key
+pub fn entry_id(key: &str) -> String {
+    format!("cite-{key}")
+}
+
+/// the entries cited in a page
+#[derive(Debug, Default)]
+pub struct Citations {
+    style: CitationStyle,
+    items: Vec<BibliographyItem>,
+}
+
+impl Citations {
+    /// collects the citations in the `nodes` of a page that get rendered, and in the footnotes they reference,
+    /// numbered in the order they are first cited
+    ///
+    /// fails if a key isn't in the bibliography
+    pub fn collect(
+        org: &Org<'_>,
+        headline: &Headline,
+        nodes: &[NodeId],
+        bibliography: Option<&Bibliography>,
+        style: CitationStyle,
+    ) -> Result<Self> {
+        let mut items: Vec<BibliographyItem> = Vec::new();
+
+        let definitions = referenced_definitions(org, headline, nodes)
+            .into_iter()
+            .flat_map(|definition| definition.descendants(org.arena()));
+        for node in nodes.iter().copied().chain(definitions) {
+            // inline footnote definitions are only parsed when they are rendered
+            let value = match &org[node] {
+                Element::Text { value } => value,
+                Element::FnRef(FnRef {
+                    definition: Some(definition),
+                    ..
+                }) => definition,
+                _ => continue,
+            };
+
+            for part in split_citations(value) {
+                let Part::Citation(citation) = part else {
+                    continue;
+                };
+                for reference in citation.references {
+                    if items.iter().any(|i| i.key == reference.key) {
+                        continue;
+                    }
+
+                    let Some(bibliography) = bibliography else {
+                        bail!(
+                            "'@{}' was cited, but there is no bibliography, set one with '#+bibliography:'",
+                            reference.key
+                        );
+                    };
+                    let Some(entry) = bibliography.get(reference.key) else {
+                        bail!(
+                            "citation key '@{}' doesn't exist in the bibliography",
+                            reference.key
+                        );
+                    };
+
+                    items.push(BibliographyItem {
+                        key: entry.key.clone(),
+                        number: items.len() + 1,
+                        authors: authors(entry),
+                        year: entry.year().unwrap_or("n.d.").to_string(),
+                        html: reference_html(entry),
+                    });
+                }
+            }
+        }
+
+        if style == CitationStyle::AuthorYear {
+            items.sort_by(|a, b| (&a.authors, &a.year).cmp(&(&b.authors, &b.year)));
+        }
+
+        Ok(Self { style, items })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BibliographyItem> {
+        self.items.iter().find(|i| i.key == key)
+    }
+
+    /// the bibliography, in the order it should be listed
+    pub fn list(&self) -> &[BibliographyItem] {
+        &self.items
+    }
+
+    /// renders a citation, linking to the entries in the bibliography
+    pub fn citation_html(&self, citation: &Citation) -> Result<String> {
+        let mut references = Vec::new();
+        for reference in &citation.references {
+            let Some(item) = self.get(reference.key) else {
+                bail!(
+                    "citation key '@{}' doesn't exist in the bibliography",
+                    reference.key
+                );
+            };
+            let id = entry_id(&item.key);
+
+            let (text, textual) = match self.style {
+                CitationStyle::AuthorYear => (
+                    format!("{} {}", item.authors, item.year),
+                    format!("{} ({})", item.authors, item.year),
+                ),
+                CitationStyle::Numeric => (
+                    item.number.to_string(),
+                    format!("{} [{}]", item.authors, item.number),
+                ),
+            };
+            let text = if citation.is_textual() { textual } else { text };
+
+            let mut html = String::new();
+            if !reference.prefix.is_empty() {
+                html += &format!("{} ", HtmlEscape(reference.prefix));
+            }
+            html += &format!("<a href=\"#{}\">{}</a>", HtmlEscape(&id), HtmlEscape(&text));
+            if !reference.suffix.is_empty() {
+                html += &format!(
+                    "{} {}",
+                    separator(reference.suffix),
+                    HtmlEscape(reference.suffix)
+                );
+            }
+            references.push(html);
+        }
+
+        let (open, separator, close) = match (citation.is_textual(), self.style) {
+            (true, _) => ("", "; ", ""),
+            (false, CitationStyle::AuthorYear) => ("(", "; ", ")"),
+            (false, CitationStyle::Numeric) => ("[", ", ", "]"),
+        };
+
+        let mut html = format!("<span class=\"citation\">{open}");
+        if !citation.prefix.is_empty() {
+            html += &format!("{} ", HtmlEscape(citation.prefix));
+        }
+        html += &references.join(separator);
+        if !citation.suffix.is_empty() {
+            html += &format!(
+                "{} {}",
+                self::separator(citation.suffix),
+                HtmlEscape(citation.suffix)
+            );
+        }
+        html += &format!("{close}</span>");
+
+        Ok(html)
+    }
+}
+
+/// suffixes like `p. 3` are separated with a comma, unless they start with punctuation
+fn separator(suffix: &str) -> &'static str {
+    if suffix.starts_with(|c: char| c.is_ascii_punctuation()) {
+        ""
+    } else {
+        ","
+    }
+}
+
+/// last names of the authors, as they are written in citations
+fn authors(entry: &Entry) -> String {
+    match entry.last_names().as_slice() {
+        [] => entry.key.clone(),
+        [one] => one.to_string(),
+        [first, second] => format!("{first} and {second}"),
+        [first, ..] => format!("{first} et al."),
+    }
+}
+
+/// the full reference, like `Knuth, Donald E. (1984). Literate Programming. The Computer Journal.`
+fn reference_html(entry: &Entry) -> String {
+    let mut html = String::new();
+
+    let names = entry.names();
+    if !names.is_empty() {
+        html += &format!("{} ", HtmlEscape(names.join("; ")));
+    }
+    html += &format!("({}). ", HtmlEscape(entry.year().unwrap_or("n.d.")));
+
+    let title = entry.get("title").unwrap_or(&entry.key);
+    html += &format!("<i>{}</i>.", HtmlEscape(title));
+
+    if let Some(container) = entry
+        .get("journal")
+        .or_else(|| entry.get("journaltitle"))
+        .or_else(|| entry.get("booktitle"))
+        .or_else(|| entry.get("publisher"))
+    {
+        html += &format!(" {}.", HtmlEscape(container));
+    }
+
+    let link = entry
+        .get("doi")
+        .map(|doi| format!("https://doi.org/{doi}"))
+        .or_else(|| entry.get("url").map(ToString::to_string));
+    if let Some(link) = link {
+        html += &format!(" <a href=\"{0}\">{0}</a>", HtmlEscape(&link));
+    }
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::Config, render::rendered_nodes};
+
+    use super::*;
+
+    #[test]
+    fn splits_citations() {
+        let parts = split_citations("see [cite/t:@knuth1984 p. 3; see @sicp] and [not a cite]");
+        assert_eq!(
+            vec![
+                Part::Text("see "),
+                Part::Citation(Citation {
+                    style: Some("t"),
+                    prefix: "",
+                    suffix: "",
+                    references: vec![
+                        Reference {
+                            prefix: "",
+                            key: "knuth1984",
+                            suffix: "p. 3"
+                        },
+                        Reference {
+                            prefix: "see",
+                            key: "sicp",
+                            suffix: ""
+                        },
+                    ],
+                }),
+                Part::Text(" and [not a cite]"),
+            ],
+            parts
+        );
+
+        assert_eq!(
+            vec![Part::Text("[cite: no keys]")],
+            split_citations("[cite: no keys]")
+        );
+    }
+
+    #[test]
+    fn lists_cited_entries() -> Result<()> {
+        let bibliography = Bibliography::parse(
+            r#"
+@article{knuth1984, author = {Knuth, Donald E.}, title = {Literate Programming},
+         journal = {The Computer Journal}, year = 1984, doi = {10.1093/comjnl/27.2.97}}
+@book{sicp, author = {Abelson, Harold and Sussman, Gerald Jay}, title = {SICP}, year = 1996}
+@book{unused, title = {Never cited}}
+"#,
+        )?;
+        let org = Org::parse("* post\n[cite:@knuth1984] then[fn:: in a note [cite:@sicp]]\n");
+        let headline = org.document().first_child(&org).unwrap();
+        let nodes = rendered_nodes(&org, &headline, false, &Config::default());
+
+        let citations = Citations::collect(
+            &org,
+            &headline,
+            &nodes,
+            Some(&bibliography),
+            CitationStyle::AuthorYear,
+        )?;
+        let keys = citations
+            .list()
+            .iter()
+            .map(|i| (i.key.as_str(), i.number))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("sicp", 2), ("knuth1984", 1)], keys);
+        assert_eq!(
+            r#"Knuth, Donald E. (1984). <i>Literate Programming</i>. The Computer Journal. <a href="https://doi.org/10.1093/comjnl/27.2.97">https://doi.org/10.1093/comjnl/27.2.97</a>"#,
+            citations.get("knuth1984").unwrap().html
+        );
+
+        let citations = Citations::collect(
+            &org,
+            &headline,
+            &nodes,
+            Some(&bibliography),
+            CitationStyle::Numeric,
+        )?;
+        assert_eq!("knuth1984", citations.list()[0].key);
+
+        assert!(Citations::collect(&org, &headline, &nodes, None, CitationStyle::Numeric).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_lists_rendered_citations() -> Result<()> {
+        let bibliography = Bibliography::parse(
+            r#"
+@book{knuth1984, author = {Knuth, Donald E.}, title = {Literate Programming}, year = 1984}
+@book{sicp, author = {Abelson, Harold and Sussman, Gerald Jay}, title = {SICP}, year = 1996}
+@book{taocp, author = {Knuth, Donald E.}, title = {TAOCP}, year = 1968}
+"#,
+        )?;
+        let source = r#"
+* index
+see[fn:1]
+** child post
+[cite:@sicp]
+** Footnotes
+[fn:1] [cite:@knuth1984]
+[fn:2] [cite:@taocp]
+"#;
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config::default();
+
+        let keys = |index| -> Result<Vec<String>> {
+            let citations = Citations::collect(
+                &org,
+                &headline,
+                &rendered_nodes(&org, &headline, index, &config),
+                Some(&bibliography),
+                CitationStyle::Numeric,
+            )?;
+            Ok(citations.list().iter().map(|i| i.key.clone()).collect())
+        };
+        // child posts aren't on the index, and unreferenced footnotes aren't rendered
+        assert_eq!(vec!["knuth1984"], keys(true)?);
+        assert_eq!(vec!["sicp", "knuth1984"], keys(false)?);
+
+        Ok(())
+    }
+}
@@ -1,10 +1,17 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use color_eyre::{eyre::ContextCompat, Result};
+use color_eyre::{
+    eyre::{bail, ContextCompat, WrapErr},
+    Result,
+};
 use orgize::Org;
+use tera::Tera;
 use vfs::{MemoryFS, VfsPath};
 
-use crate::args::Args;
+use crate::{
+    args::Args, bibliography::Bibliography, citations::CitationStyle, syntaxes::Syntaxes,
+    tera::make_block_tera, timestamps::validate_format,
+};
 
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
@@ -17,19 +24,77 @@ pub struct Config {
     pub build_path: VfsPath,
     pub static_path: VfsPath,
     pub templates_path: VfsPath,
+    /// where results of executed source blocks are cached, set with `#+cache:`
+    pub cache_path: VfsPath,
 
     pub verbose: bool,
     pub release: bool,
     pub hotreloading: bool,
+    /// whether source blocks can be executed, only with `--allow-exec`
+    pub allow_exec: bool,
 
     pub preamble: HashMap<String, String>,
     pub url: String,
+    /// the url the site is published at, from `#+url:`, while `url` is the local server's outside of release builds
+    pub site_url: Option<String>,
     pub title: String,
     pub description: String,
 
+    /// `target` for links to other sites, set with `#+external_link_target:`, and `none` to open them in the same tab
+    pub external_link_target: Option<String>,
+    /// embed youtube links that are alone in their paragraph, set with `#+embed_youtube: t`
+    pub embed_youtube: bool,
+    /// hosts of peertube instances whose video links are embedded, set with `#+peertube_instances:`
+    pub peertube_instances: Vec<String>,
+
+    /// widths of the resized copies made of images in the static folder, set with `#+image_widths: 480 960`
+    pub image_widths: Vec<u32>,
+    /// `sizes` of images with resized copies, set with `#+image_sizes:`
+    pub image_sizes: String,
+    /// width of the thumbnails of gallery pages, set with `#+gallery_thumbnail_width:`
+    pub gallery_thumbnail_width: u32,
+
+    /// render footnotes as sidenotes next to their references, set with `#+footnotes: sidenotes`
+    ///
+    /// can be overriden per page with the `sidenotes` property
+    pub sidenotes: bool,
+
+    /// entries that can be cited with `[cite:@key]`, from the bibtex file set with `#+bibliography:`
+    pub bibliography: Option<Arc<Bibliography>>,
+    /// set with `#+citation_style:`, either `author-year` or `numeric`
+    pub citation_style: CitationStyle,
+
+    /// whether post subheadlines get hierarchical numbers, set with `#+OPTIONS: num:t`
+    ///
+    /// can be overriden per page with the `numbered` property
+    pub section_numbers: bool,
+
+    /// curly quotes, dashes and ellipses in text, set with `#+OPTIONS: ':t`
+    pub smart_typography: bool,
+    /// set with `#+LANGUAGE:`, decides which spaces typography makes non-breaking
+    pub language: Option<String>,
+
+    /// `strftime` format for timestamps without a time, set with `#+timestamp_format:`
+    pub timestamp_format: String,
+    /// `strftime` format for timestamps with a time, set with `#+timestamp_time_format:`
+    pub timestamp_time_format: String,
+
     /// One of: base16-ocean.dark, base16-eighties.dark, base16-mocha.dark, base16-ocean.light,
-    /// InspiredGitHub, Solarized (dark), Solarized (light)
+    /// InspiredGitHub, Solarized (dark), Solarized (light), or a theme from the `syntaxes` folder
+    ///
+    /// used as the light theme when highlighting with classes
     pub syntax_highlighting_theme: String,
+    /// theme for `syntax-dark.css` when highlighting with classes
+    pub syntax_highlighting_dark_theme: String,
+    /// highlight code with classes instead of inline styles, set with `#+syntax_highlighting: classes`
+    ///
+    /// `syntax-light.css` and `syntax-dark.css` are written into the build folder
+    pub syntax_highlighting_classes: bool,
+    /// bundled syntaxes and themes, plus the ones in the folder set with `#+syntaxes:`
+    pub syntaxes: Arc<Syntaxes>,
+
+    /// templates for special blocks, from `blocks/{name}.html` in the templates folder
+    pub block_templates: Arc<Tera>,
 }
 
 impl Default for Config {
@@ -42,17 +107,46 @@ impl Default for Config {
             build_path: VfsPath::new(MemoryFS::new()),
             static_path: VfsPath::new(MemoryFS::new()),
             templates_path: VfsPath::new(MemoryFS::new()),
+            cache_path: VfsPath::new(MemoryFS::new()),
 
             verbose: false,
             release: false,
             hotreloading: false,
+            allow_exec: false,
 
             preamble: Default::default(),
             url: Default::default(),
+            site_url: None,
             title: Default::default(),
             description: Default::default(),
 
+            external_link_target: Some("_blank".to_string()),
+            embed_youtube: false,
+            peertube_instances: vec![],
+
+            image_widths: vec![],
+            image_sizes: "100vw".to_string(),
+            gallery_thumbnail_width: 400,
+
+            sidenotes: false,
+
+            bibliography: None,
+            citation_style: Default::default(),
+
+            section_numbers: false,
+
+            smart_typography: false,
+            language: None,
+
+            timestamp_format: "%Y-%m-%d".to_string(),
+            timestamp_time_format: "%Y-%m-%d %H:%M".to_string(),
+
             syntax_highlighting_theme: "InspiredGitHub".to_string(),
+            syntax_highlighting_dark_theme: "base16-ocean.dark".to_string(),
+            syntax_highlighting_classes: false,
+            syntaxes: Syntaxes::defaults(),
+
+            block_templates: Default::default(),
         }
     }
 }
@@ -103,10 +197,116 @@ impl Config {
             path
         };
 
+        let cache_path = fs
+            .clone()
+            .join(preamble.get("cache").unwrap_or(&".cache"))?;
+
+        let external_link_target = match preamble.get("external_link_target") {
+            Some(&"none") => None,
+            Some(target) => Some(target.to_string()),
+            None => Some("_blank".to_string()),
+        };
+
+        let embed_youtube = preamble.get("embed_youtube") == Some(&"t");
+        let peertube_instances = preamble
+            .get("peertube_instances")
+            .map(|hosts| hosts.split_whitespace().map(ToString::to_string).collect())
+            .unwrap_or_default();
+
+        let image_widths = preamble
+            .get("image_widths")
+            .map(|widths| {
+                widths
+                    .split_whitespace()
+                    .map(|width| {
+                        width
+                            .parse()
+                            .wrap_err_with(|| format!("invalid image width '{width}'"))
+                    })
+                    .collect::<Result<Vec<u32>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let image_sizes = preamble.get("image_sizes").unwrap_or(&"100vw").to_string();
+        let gallery_thumbnail_width = match preamble.get("gallery_thumbnail_width") {
+            Some(width) => width
+                .parse()
+                .wrap_err_with(|| format!("invalid gallery thumbnail width '{width}'"))?,
+            None => 400,
+        };
+
+        let sidenotes = preamble.get("footnotes") == Some(&"sidenotes");
+
+        let bibliography = match preamble.get("bibliography") {
+            Some(file) => {
+                let source = fs
+                    .join(file)?
+                    .read_to_string()
+                    .wrap_err_with(|| format!("failed to read bibliography '{file}'"))?;
+                let bibliography = Bibliography::parse(&source)
+                    .wrap_err_with(|| format!("failed to parse bibliography '{file}'"))?;
+                Some(Arc::new(bibliography))
+            }
+            None => None,
+        };
+        let citation_style = match preamble.get("citation_style") {
+            Some(style) => CitationStyle::parse(style)?,
+            None => Default::default(),
+        };
+
+        let options = preamble
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("options"))
+            .map(|(_, options)| options.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let section_numbers = options.contains(&"num:t");
+        let smart_typography = options.contains(&"':t");
+
+        let language = preamble
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("language"))
+            .map(|(_, language)| language.to_string());
+
+        let timestamp_format = preamble
+            .get("timestamp_format")
+            .unwrap_or(&"%Y-%m-%d")
+            .to_string();
+        let timestamp_time_format = preamble
+            .get("timestamp_time_format")
+            .unwrap_or(&"%Y-%m-%d %H:%M")
+            .to_string();
+        validate_format(&timestamp_format)?;
+        validate_format(&timestamp_time_format)?;
+
         let syntax_highlighting_theme = preamble
             .get("syntax_highlighting_theme")
             .unwrap_or(&"InspiredGitHub")
             .to_string();
+        let syntax_highlighting_dark_theme = preamble
+            .get("syntax_highlighting_dark_theme")
+            .unwrap_or(&"base16-ocean.dark")
+            .to_string();
+        let syntax_highlighting_classes = preamble.get("syntax_highlighting") == Some(&"classes");
+
+        let syntaxes = match preamble.get("syntaxes") {
+            Some(folder) => Syntaxes::load(&fs.join(folder)?)?,
+            None => Syntaxes::defaults(),
+        };
+        for theme in [&syntax_highlighting_theme, &syntax_highlighting_dark_theme] {
+            if !syntaxes.themes.contains_key(theme) {
+                bail!(
+                    "Syntax highlighting theme '{theme}' doesn't exist, available themes are: {}",
+                    syntaxes
+                        .themes
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+
+        let block_templates = Arc::new(make_block_tera(&templates_path)?);
 
         let config = Self {
             root_folder: args.root_folder(),
@@ -116,20 +316,49 @@ impl Config {
             build_path: build_path.clone(),
             static_path: static_path.clone(),
             templates_path: templates_path.clone(),
+            cache_path,
 
             verbose: args.verbose,
             release: args.is_release(),
             hotreloading: args.is_hotreloading(),
+            allow_exec: args.allow_exec,
 
             preamble: preamble
                 .iter()
                 .map(|(a, b)| (a.to_string(), b.to_string()))
                 .collect(),
             url: url.to_string(),
+            site_url: preamble.get("url").map(ToString::to_string),
             title: title.to_string(),
             description: description.to_string(),
 
+            external_link_target,
+            embed_youtube,
+            peertube_instances,
+
+            image_widths,
+            image_sizes,
+            gallery_thumbnail_width,
+
+            sidenotes,
+
+            bibliography,
+            citation_style,
+
+            section_numbers,
+
+            smart_typography,
+            language,
+
+            timestamp_format,
+            timestamp_time_format,
+
             syntax_highlighting_theme,
+            syntax_highlighting_dark_theme,
+            syntax_highlighting_classes,
+            syntaxes,
+
+            block_templates,
         };
         Ok(config)
     }
@@ -190,5 +419,136 @@ mod tests {
         assert_eq!("this is a title", config.title);
         assert_eq!("this is a description", config.description);
         assert_eq!("a url here", config.url);
+        assert_eq!(Some("a url here".to_string()), config.site_url);
+        assert!(!config.section_numbers);
+    }
+
+    #[test]
+    fn can_parse_section_numbers_option() {
+        let source = r#"
+#+title: this is a title
+#+description: this is a description
+#+url: a url here
+#+OPTIONS: toc:nil num:t
+"#;
+
+        let fs: VfsPath = MemoryFS::new().into();
+        let args = Args::default();
+        let org = Org::parse(source);
+
+        let config = Config::new(&fs, &args, &org).unwrap();
+
+        assert!(config.section_numbers);
+        assert!(!config.smart_typography);
+    }
+
+    #[test]
+    fn can_parse_typography_options() {
+        let source = r#"
+#+title: this is a title
+#+description: this is a description
+#+url: a url here
+#+OPTIONS: ':t
+#+LANGUAGE: fr
+"#;
+
+        let fs: VfsPath = MemoryFS::new().into();
+        let args = Args::default();
+        let org = Org::parse(source);
+
+        let config = Config::new(&fs, &args, &org).unwrap();
+
+        assert!(config.smart_typography);
+        assert_eq!(Some("fr"), config.language.as_deref());
+    }
+
+    #[test]
+    fn fails_on_unknown_theme() {
+        let source = r#"
+#+title: this is a title
+#+description: this is a description
+#+url: a url here
+#+syntax_highlighting_theme: nope
+"#;
+
+        let fs: VfsPath = MemoryFS::new().into();
+        let args = Args::default();
+        let org = Org::parse(source);
+
+        let err = Config::new(&fs, &args, &org).unwrap_err().to_string();
+
+        assert!(err.starts_with("Syntax highlighting theme 'nope' doesn't exist"));
+        assert!(err.contains("InspiredGitHub"));
+    }
+
+    #[test]
+    fn loads_themes_from_syntaxes_folder() -> Result<()> {
+        let source = r#"
+#+title: this is a title
+#+description: this is a description
+#+url: a url here
+#+syntaxes: syntaxes
+#+syntax_highlighting_theme: brand
+"#;
+
+        let fs: VfsPath = MemoryFS::new().into();
+        fs.join("syntaxes")?.create_dir_all()?;
+        fs.join("syntaxes/brand.tmTheme")?
+            .create_file()?
+            .write_all(BRAND_THEME.as_bytes())?;
+        let args = Args::default();
+        let org = Org::parse(source);
+
+        let config = Config::new(&fs, &args, &org)?;
+
+        assert_eq!("brand", config.syntax_highlighting_theme);
+        assert!(config.syntaxes.themes.contains_key("brand"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn loads_bibliography() -> Result<()> {
+        let source = r#"
+#+title: this is a title
+#+description: this is a description
+#+url: a url here
+#+bibliography: refs.bib
+#+citation_style: numeric
+"#;
+
+        let fs: VfsPath = MemoryFS::new().into();
+        fs.join("refs.bib")?
+            .create_file()?
+            .write_all(b"@book{sicp, title = {SICP}}")?;
+        let args = Args::default();
+        let org = Org::parse(source);
+
+        let config = Config::new(&fs, &args, &org)?;
+
+        assert!(config.bibliography.unwrap().get("sicp").is_some());
+        assert_eq!(CitationStyle::Numeric, config.citation_style);
+
+        Ok(())
     }
+
+    const BRAND_THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>brand</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#ff00ff</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
 }
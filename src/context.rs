@@ -3,32 +3,54 @@ use std::{collections::HashMap, fs::File, io::Read, path::Path, rc::Rc};
 use color_eyre::{eyre::WrapErr, Result};
 use orgize::{Headline, Org};
 use serde_derive::Serialize;
+use slugmin::slugify;
 use tera::Context;
 
 use crate::{
+    citations::Citations,
     count_words::*,
-    footnotes::*,
+    crossrefs::CrossReferences,
+    embeds::Embeds,
+    footnotes::Footnotes,
+    gallery::get_gallery,
+    lists::Lists,
     macros::Macros,
     page::{Page, PageEnum},
     render::*,
+    tangle::{get_tangled_files, TangledFile},
     Config,
 };
 
 impl Page<'_> {
+    /// the context to render the page's template with, and the files tangled from its source blocks
     pub fn page_context(
         &self,
         org: &Org<'_>,
         macros: Rc<Macros>,
         config: &Config,
-    ) -> Result<Context> {
-        let mut context = match &self.page {
-            PageEnum::Index { children } => {
-                get_index_context(&self.headline, org, children, macros, config)
-            }
-            PageEnum::Post => get_post_context(&self.headline, org, macros, config),
-            PageEnum::OrgFile { path } => {
-                get_org_file_context(&self.headline, org, path, macros, config)?
-            }
+    ) -> Result<(Context, Vec<TangledFile>)> {
+        let (mut context, tangled_files) = match &self.page {
+            PageEnum::Index { children } => (
+                get_index_context(&self.headline, org, children, &self.path, macros, config)?,
+                vec![],
+            ),
+            PageEnum::Post => get_post_context(
+                &self.headline,
+                org,
+                &self.path,
+                &self.info.slug,
+                macros,
+                config,
+            )?,
+            PageEnum::OrgFile { path } => get_org_file_context(
+                &self.headline,
+                org,
+                path,
+                &self.path,
+                &self.info.slug,
+                macros,
+                config,
+            )?,
         };
 
         context.insert("asset_v", &rand::random::<u16>());
@@ -44,7 +66,14 @@ impl Page<'_> {
             context.insert(k.clone(), &v);
         }
 
-        Ok(context)
+        // replaces the property with the images it points to
+        if let Some(folder) = self.info.properties.get("gallery") {
+            let gallery = get_gallery(config, folder)
+                .wrap_err_with(|| format!("in headline '{}'", self.info.title))?;
+            context.insert("gallery", &gallery);
+        }
+
+        Ok((context, tangled_files))
     }
 }
 
@@ -57,13 +86,125 @@ pub struct PageLink<'a> {
     closed_at: Option<String>,
 }
 
+#[derive(Serialize, Debug)]
+pub struct TocEntry {
+    title: String,
+    slug: String,
+    /// depth below the page title, starting at 1
+    level: usize,
+    number: Option<String>,
+}
+
+/// whether the subheadlines of this page should be numbered
+///
+/// the `numbered` property takes precedence over `#+OPTIONS: num:t`
+fn is_numbered(headline: &Headline, org: &Org<'_>, config: &Config) -> bool {
+    headline
+        .title(org)
+        .properties
+        .iter()
+        .find(|(k, _)| k == "numbered")
+        .map(|(_, v)| v != "nil")
+        .unwrap_or(config.section_numbers)
+}
+
+/// whether footnotes of this page are rendered as sidenotes
+///
+/// the `sidenotes` property takes precedence over `#+footnotes: sidenotes`
+fn uses_sidenotes(headline: &Headline, org: &Org<'_>, config: &Config) -> bool {
+    headline
+        .title(org)
+        .properties
+        .iter()
+        .find(|(k, _)| k == "sidenotes")
+        .map(|(_, v)| v != "nil")
+        .unwrap_or(config.sidenotes)
+}
+
+/// lists every subheadline of a post in document order, numbered the same way `PostHtmlHandler` does
+fn get_toc(headline: &Headline, org: &Org<'_>, numbered: bool) -> Vec<TocEntry> {
+    fn add(
+        headline: &Headline,
+        org: &Org<'_>,
+        page_level: usize,
+        numbers: &mut Option<SectionNumbers>,
+        toc: &mut Vec<TocEntry>,
+    ) {
+        for child in headline.children(org) {
+            let title = child.title(org);
+            let level = child.level() - page_level;
+            toc.push(TocEntry {
+                title: title.raw.to_string(),
+                slug: slugify(&title.raw),
+                level,
+                number: numbers.as_mut().map(|n| n.next(level)),
+            });
+            add(&child, org, page_level, numbers, toc);
+        }
+    }
+
+    let mut toc = Vec::new();
+    let mut numbers = numbered.then(SectionNumbers::default);
+    add(headline, org, headline.level(), &mut numbers, &mut toc);
+    toc
+}
+
+/// makes the handler to render a page with, after collecting what it needs to know ahead of time, like footnotes
+///
+/// sidenotes go inside of paragraphs, so their definitions can't contain any
+///
+/// lists and cross references are keyed by where the elements of `org` are in memory (see [`crate::helpers::ElementKey`]),
+/// so the handler can only render this `org`, and it can't be modified while the handler is used
+pub fn common_handler(
+    headline: &Headline,
+    org: &Org<'_>,
+    page_path: &str,
+    index: bool,
+    sidenotes: bool,
+    macros: Rc<Macros>,
+    config: &Config,
+) -> Result<CommonHtmlHandler> {
+    let nodes = rendered_nodes(org, headline, index, config);
+    let citations = Rc::new(Citations::collect(
+        org,
+        headline,
+        &nodes,
+        config.bibliography.as_deref(),
+        config.citation_style,
+    )?);
+    let cross_references = Rc::new(CrossReferences::collect(org, &nodes, config));
+    let lists = Rc::new(Lists::collect(org, headline));
+    let embeds = Rc::new(Embeds::collect(org, &nodes, config));
+    let footnotes = Footnotes::collect(org, headline, &nodes, || CommonHtmlHandler {
+        inline_paragraphs: sidenotes,
+        headline: headline.title(org).raw.to_string(),
+        citations: citations.clone(),
+        cross_references: cross_references.clone(),
+        lists: lists.clone(),
+        embeds: embeds.clone(),
+        ..CommonHtmlHandler::new(config, macros.clone(), page_path)
+    })?;
+
+    Ok(CommonHtmlHandler {
+        footnotes: Rc::new(footnotes),
+        headline: headline.title(org).raw.to_string(),
+        sidenotes,
+        citations,
+        cross_references,
+        lists,
+        embeds,
+        ..CommonHtmlHandler::new(config, macros, page_path)
+    })
+}
+
 fn get_index_context(
     headline: &Headline,
     org: &Org<'_>,
     children: &HashMap<String, Page>,
+    page_path: &str,
     macros: Rc<Macros>,
     config: &Config,
-) -> Context {
+) -> Result<Context> {
     let mut pages = children
         .iter()
         .map(|(slug, page)| PageLink {
@@ -74,34 +215,34 @@ fn get_index_context(
             closed_at: page.info.closed_at(),
         })
         .collect::<Vec<_>>();
-    pages.sort_unstable_by(|a, b| a.order.cmp(&b.order));
+    pages.sort_by_key(|a| a.order);
 
+    let sidenotes = uses_sidenotes(headline, org, config);
+    let handler = common_handler(headline, org, page_path, true, sidenotes, macros, config)?;
+    let footnotes = handler.footnotes.clone();
+    let citations = handler.citations.clone();
     let html = write_html(
         headline,
         org,
         IndexHtmlHandler {
             level: headline.level(),
-            handler: CommonHtmlHandler {
-                handler: html_handler(config.syntax_highlighting_theme.clone()),
-                config: config.clone(),
-                attributes: Default::default(),
-                footnote_id: 0,
-                macros,
-            },
+            handler,
             in_headline: false,
             in_page_title: false,
         },
-    );
+    )?;
 
     let mut context = Context::new();
     context.insert("content", &html);
     context.insert("pages", &pages);
+    context.insert("footnotes", footnotes.list());
+    context.insert("bibliography", citations.list());
 
     let word_count = count_words_index(headline, org);
     context.insert("word_count", &word_count);
     context.insert("reading_time", &(word_count / 180).max(1));
 
-    context
+    Ok(context)
 }
 
 /// generates the context for a blog post
@@ -110,49 +251,65 @@ fn get_index_context(
 fn get_post_context(
     headline: &Headline,
     org: &Org<'_>,
+    page_path: &str,
+    slug: &str,
     macros: Rc<Macros>,
     config: &Config,
-) -> Context {
+) -> Result<(Context, Vec<TangledFile>)> {
     let sections = headline
         .children(org)
         .map(|h| h.title(org).raw.clone())
         .collect::<Vec<_>>();
 
+    let numbered = is_numbered(headline, org, config);
+    let toc = get_toc(headline, org, numbered);
+
     let mut context = Context::new();
 
+    let sidenotes = uses_sidenotes(headline, org, config);
+    let handler = common_handler(headline, org, page_path, false, sidenotes, macros, config)?;
+    let footnotes = handler.footnotes.clone();
+    let citations = handler.citations.clone();
     let handler = PostHtmlHandler {
         level: headline.level(),
-        handler: CommonHtmlHandler {
-            handler: html_handler(config.syntax_highlighting_theme.clone()),
-            config: config.clone(),
-            attributes: Default::default(),
-            footnote_id: 0,
-            macros,
-        },
+        handler,
         in_page_title: false,
+        section_numbers: numbered.then(SectionNumbers::default),
     };
-    let html = write_html(headline, org, handler);
+    let html = write_html(headline, org, handler)?;
 
     context.insert("content", &html);
     context.insert("sections", &sections);
+    context.insert("toc", &toc);
 
     let word_count = count_words_post(headline, org);
     context.insert("word_count", &word_count);
     context.insert("reading_time", &(word_count / 180).max(1));
 
-    let footnotes = get_footnotes(org, headline);
-    context.insert("footnotes", &footnotes);
+    context.insert("footnotes", footnotes.list());
+    context.insert("bibliography", citations.list());
+
+    let tangled_files = get_tangled_files(
+        org,
+        headline,
+        &format!("{}{page_path}", config.url),
+        slug,
+        &config.syntaxes.syntax_set,
+    )?;
+    context.insert("tangled_files", &tangled_files);
 
-    context
+    Ok((context, tangled_files))
 }
 
 fn get_org_file_context(
     headline: &Headline,
     org: &Org<'_>,
     file: &Path,
+    page_path: &str,
+    slug: &str,
     macros: Rc<Macros>,
     config: &Config,
-) -> Result<Context> {
+) -> Result<(Context, Vec<TangledFile>)> {
     let sections = headline
         .children(org)
         .map(|h| h.title(org).raw.clone())
@@ -176,31 +333,124 @@ fn get_org_file_context(
     let doc = new_org.document();
     let first = doc.first_child(&new_org).unwrap();
 
+    let numbered = is_numbered(headline, org, config);
+    let toc = get_toc(&first, &new_org, numbered);
+
+    let sidenotes = uses_sidenotes(headline, org, config);
+    let handler = common_handler(
+        &first, &new_org, page_path, false, sidenotes, macros, config,
+    )?;
+    let footnotes = handler.footnotes.clone();
+    let citations = handler.citations.clone();
     let html = write_html(
         &first,
         &new_org,
         PostHtmlHandler {
             level: first.level(),
-            handler: CommonHtmlHandler {
-                handler: html_handler(config.syntax_highlighting_theme.clone()),
-                config: config.clone(),
-                attributes: Default::default(),
-                footnote_id: 0,
-                macros,
-            },
+            handler,
             in_page_title: false,
+            section_numbers: numbered.then(SectionNumbers::default),
         },
-    );
+    )?;
 
     context.insert("content", &html);
     context.insert("sections", &sections);
+    context.insert("toc", &toc);
 
     let word_count = count_words_post(&first, org);
     context.insert("word_count", &word_count);
     context.insert("reading_time", &(word_count / 180).max(1));
 
-    let footnotes = get_footnotes(org, headline);
-    context.insert("footnotes", &footnotes);
+    context.insert("footnotes", footnotes.list());
+    context.insert("bibliography", citations.list());
 
-    Ok(context)
+    let tangled_files = get_tangled_files(
+        &new_org,
+        &first,
+        &format!("{}{page_path}", config.url),
+        slug,
+        &config.syntaxes.syntax_set,
+    )?;
+    context.insert("tangled_files", &tangled_files);
+
+    Ok((context, tangled_files))
+}
+
+#[cfg(test)]
+mod tests {
+    use tera::Tera;
+
+    use crate::config::TODO_KEYWORDS;
+
+    use super::*;
+
+    #[test]
+    fn renders_sidenotes_with_the_default_template() -> Result<()> {
+        let source = r#"
+* index
+** post :post:
+:PROPERTIES:
+:sidenotes: t
+:END:
+see[fn:a]
+
+[fn:a] the note
+"#;
+        let org = Org::parse(source);
+        let index = Page::parse_index(
+            &org,
+            org.document().first_child(&org).unwrap(),
+            &TODO_KEYWORDS,
+            String::new(),
+            0,
+            false,
+        );
+        let PageEnum::Index { children } = &index.page else {
+            panic!("the first headline is an index");
+        };
+        let (context, _) =
+            children["post"].page_context(&org, Default::default(), &Config::default())?;
+
+        let tera = Tera::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/templates/*.html"
+        ))?;
+        let html = tera.render("default.html", &context)?;
+        assert!(html.contains(r#"class="sidenote"><span  class="paragraph" > the note"#));
+        assert!(html.contains(r#"<li id="fn-a">"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn numbered_headings_match_toc() -> Result<()> {
+        let source = "* post\n:PROPERTIES:\n:numbered: t\n:END:\n** one\n*** one a\n*** one b\n** two\n**** deep\n** three\n";
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let (context, _) = get_post_context(
+            &headline,
+            &org,
+            "/blog/post",
+            "post",
+            Default::default(),
+            &Config::default(),
+        )?;
+
+        let toc = context.get("toc").unwrap().as_array().unwrap();
+        let numbers = toc
+            .iter()
+            .map(|entry| entry["number"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["1", "1.1", "1.2", "2", "2.0.1", "3"], numbers);
+
+        let html = context.get("content").unwrap().as_str().unwrap();
+        let headings = html
+            .split("<span class=\"section-number\">")
+            .skip(1)
+            .map(|rest| rest.split('<').next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, headings);
+
+        Ok(())
+    }
 }
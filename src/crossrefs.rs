@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use orgize::{elements::Table, indextree::NodeId, Element, Org};
+
+use crate::{
+    config::Config,
+    helpers::ElementKey,
+    render::{is_image, source_block_output},
+    source_block::HeaderArgs,
+};
+
+/// the kinds of elements that are numbered when they have a `#+CAPTION:`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TargetKind {
+    Figure,
+    Table,
+    Listing,
+}
+
+impl TargetKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            TargetKind::Figure => "Figure",
+            TargetKind::Table => "Table",
+            TargetKind::Listing => "Listing",
+        }
+    }
+}
+
+/// an element with a `#+NAME:`, which links like `[[name]]` point to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub kind: TargetKind,
+    /// only captioned elements are numbered
+    pub number: Option<usize>,
+}
+
+impl Target {
+    /// text for links to this element, like `Figure 2`
+    pub fn label(&self) -> Option<String> {
+        self.number.map(|n| format!("{} {n}", self.kind.label()))
+    }
+}
+
+/// the named and numbered elements of a page, so they can be referenced before they appear
+#[derive(Debug, Default)]
+pub struct CrossReferences {
+    targets: HashMap<String, Target>,
+    /// the captioned elements, with their numbers
+    elements: HashMap<ElementKey, Target>,
+}
+
+impl CrossReferences {
+    /// numbers the images, tables and source blocks that get rendered, per kind and in order
+    ///
+    /// `nodes` are the ones the page renders, see [`crate::render::rendered_nodes`]
+    pub fn collect(org: &Org<'_>, nodes: &[NodeId], config: &Config) -> Self {
+        let mut refs = Self::default();
+        let mut counts: HashMap<TargetKind, usize> = HashMap::new();
+
+        let mut name = None;
+        let mut captioned = false;
+        for &node in nodes {
+            let element = &org[node];
+            let kind = match element {
+                Element::Keyword(keyword) => {
+                    match keyword.key.to_lowercase().as_str() {
+                        "name" => name = Some(keyword.value.to_string()),
+                        "caption" => captioned = true,
+                        _ => {}
+                    }
+                    continue;
+                }
+                // keywords before a paragraph apply to an image at its start
+                Element::Paragraph { .. } => continue,
+                Element::Link(link) if is_image(&link.path.to_lowercase()) => {
+                    Some(TargetKind::Figure)
+                }
+                Element::Table(Table::Org { .. }) => Some(TargetKind::Table),
+                Element::SourceBlock(block) => {
+                    let output =
+                        source_block_output(block, &HeaderArgs::parse(&block.arguments), config);
+                    if output.diagram {
+                        Some(TargetKind::Figure)
+                    } else if output.code && !block.language.is_empty() {
+                        Some(TargetKind::Listing)
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some(kind) = kind {
+                let number = captioned.then(|| {
+                    let n = counts.entry(kind).or_default();
+                    *n += 1;
+                    *n
+                });
+                let target = Target { kind, number };
+                if captioned {
+                    refs.elements
+                        .insert(ElementKey::of(element), target.clone());
+                }
+                if let Some(name) = name.take() {
+                    refs.targets.insert(name, target);
+                }
+            }
+            name = None;
+            captioned = false;
+        }
+
+        refs
+    }
+
+    /// the number of a captioned element, if it's rendered
+    pub fn element(&self, element: &Element) -> Option<&Target> {
+        self.elements.get(&ElementKey::of(element))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Target> {
+        self.targets.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::rendered_nodes;
+
+    #[test]
+    fn numbers_captioned_elements() {
+        let source = r#"
+* post
+#+NAME: cat
+#+CAPTION: a cat
+[[./cat.png]]
+
+#+NAME: uncaptioned
+[[./dog.png]]
+
+#+CAPTION: some numbers
+#+NAME: numbers
+| 1 | 2 |
+
+#+CAPTION: not an image
+[[https://example.com]]
+
+#+NAME: hello
+#+CAPTION: hello world
+#+begin_src rust
+fn main() {}
+#+end_src
+
+#+CAPTION: a bird
+[[./bird.png]]
+
+#+NAME: graph
+#+CAPTION: a graph
+#+begin_src dot :exports results
+digraph { a -> b }
+#+end_src
+"#;
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let refs = CrossReferences::collect(
+            &org,
+            &rendered_nodes(&org, &headline, false, &Config::default()),
+            &Config::default(),
+        );
+
+        let label = |name| refs.get(name).and_then(Target::label);
+        assert_eq!(Some("Figure 1".to_string()), label("cat"));
+        assert_eq!(Some("Table 1".to_string()), label("numbers"));
+        assert_eq!(Some("Listing 1".to_string()), label("hello"));
+        assert_eq!(Some("Figure 3".to_string()), label("graph"));
+
+        let uncaptioned = refs.get("uncaptioned").unwrap();
+        assert_eq!(TargetKind::Figure, uncaptioned.kind);
+        assert_eq!(None, uncaptioned.label());
+
+        assert!(refs.get("missing").is_none());
+    }
+
+    #[test]
+    fn only_numbers_rendered_elements() {
+        let source = r#"
+* index
+#+begin_src sh
+echo hidden
+#+end_src
+
+#+CAPTION: hidden results
+#+RESULTS:
+| hidden |
+
+#+NAME: numbers
+#+CAPTION: some numbers
+| 1 | 2 |
+
+#+NAME: cat
+#+CAPTION: a cat
+[[./cat.png]]
+
+** child page
+#+CAPTION: not on the index
+| 3 | 4 |
+"#;
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let refs = CrossReferences::collect(
+            &org,
+            &rendered_nodes(&org, &headline, true, &Config::default()),
+            &Config::default(),
+        );
+
+        let label = |name| refs.get(name).and_then(Target::label);
+        assert_eq!(Some("Table 1".to_string()), label("numbers"));
+        assert_eq!(Some("Figure 1".to_string()), label("cat"));
+
+        let numbers = headline
+            .headline_node()
+            .descendants(org.arena())
+            .filter_map(|node| refs.element(&org[node]))
+            .filter_map(Target::label)
+            .collect::<Vec<_>>();
+        // the hidden results and the child page aren't numbered
+        assert_eq!(vec!["Table 1", "Figure 1"], numbers);
+    }
+}
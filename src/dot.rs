@@ -0,0 +1,41 @@
+use color_eyre::{eyre::eyre, Result};
+use layout::{
+    backends::svg::SVGWriter,
+    gv::{DotParser, GraphBuilder},
+};
+
+/// lays out a graphviz dot graph, returning an svg that can be inlined into html
+pub fn render_dot(source: &str) -> Result<String> {
+    let graph = DotParser::new(source)
+        .process()
+        .map_err(|err| eyre!("failed to parse dot graph: {err}"))?;
+
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut visual_graph = builder.get();
+
+    let mut svg = SVGWriter::new();
+    visual_graph.do_it(false, false, false, &mut svg);
+
+    // the xml declaration is only valid at the start of a standalone file
+    let svg = svg.finalize();
+    let start = svg.find("<svg").unwrap_or(0);
+    Ok(svg[start..].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_svg() {
+        let svg = render_dot("digraph { a -> b }").unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert!(render_dot("digraph { a -> }").is_err());
+    }
+}
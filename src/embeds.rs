@@ -0,0 +1,178 @@
+use std::collections::{HashMap, HashSet};
+
+use orgize::{indextree::NodeId, Element, Org};
+
+use crate::{config::Config, helpers::ElementKey};
+
+/// the video links of a page that get embedded, which are the ones alone in their paragraph
+#[derive(Debug, Default)]
+pub struct Embeds {
+    /// the url to embed for each link
+    links: HashMap<ElementKey, String>,
+    /// the paragraphs the links are in, which aren't written since an embed can't go inside of a `<p>`
+    paragraphs: HashSet<ElementKey>,
+}
+
+impl Embeds {
+    /// finds the links without a description that are the only thing in their paragraph, out of the `nodes` that get rendered
+    pub fn collect(org: &Org<'_>, nodes: &[NodeId], config: &Config) -> Self {
+        let mut embeds = Self::default();
+
+        for &paragraph in nodes {
+            if !matches!(org[paragraph], Element::Paragraph { .. }) {
+                continue;
+            }
+
+            let mut links = Vec::new();
+            let mut alone = true;
+            for child in paragraph.children(org.arena()) {
+                match &org[child] {
+                    Element::Text { value } if value.trim().is_empty() => {}
+                    Element::Link(link) if link.desc.is_none() => links.push((child, link)),
+                    _ => alone = false,
+                }
+            }
+
+            if let ([(node, link)], true) = (links.as_slice(), alone) {
+                if let Some(url) = embed_url(&link.path, config) {
+                    embeds.links.insert(ElementKey::of(&org[*node]), url);
+                    embeds.paragraphs.insert(ElementKey::of(&org[paragraph]));
+                }
+            }
+        }
+
+        embeds
+    }
+
+    pub fn link(&self, element: &Element) -> Option<&str> {
+        self.links.get(&ElementKey::of(element)).map(String::as_str)
+    }
+
+    pub fn paragraph(&self, element: &Element) -> bool {
+        self.paragraphs.contains(&ElementKey::of(element))
+    }
+}
+
+/// the url to embed a video from a youtube or peertube link
+///
+/// youtube links are only embedded when it's turned on, and peertube instances can be on any domain,
+/// so only links to the configured hosts are embedded
+fn embed_url(link: &str, config: &Config) -> Option<String> {
+    let url = url::Url::parse(link).ok()?;
+    let host = url.host_str()?;
+    let mut segments = url.path_segments()?;
+
+    let youtube_id = match host.trim_start_matches("www.").trim_start_matches("m.") {
+        "youtube.com" | "youtube-nocookie.com" if config.embed_youtube => match segments.next()? {
+            "watch" => url
+                .query_pairs()
+                .find(|(k, _)| k == "v")
+                .map(|(_, v)| v.to_string()),
+            "shorts" | "embed" | "live" => segments.next().map(ToString::to_string),
+            _ => None,
+        },
+        "youtu.be" if config.embed_youtube => segments.next().map(ToString::to_string),
+        _ if config
+            .peertube_instances
+            .iter()
+            .any(|instance| instance == host) =>
+        {
+            // videos are at `/w/{id}` or `/videos/watch/{id}`, with ids that are at least 22 characters long
+            let id = match (segments.next(), segments.next(), segments.next()) {
+                (Some("w"), Some(id), None) | (Some("videos"), Some("watch"), Some(id)) => id,
+                _ => return None,
+            };
+            if id.len() < 22 || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return None;
+            }
+            return Some(format!("{}://{host}/videos/embed/{id}", url.scheme()));
+        }
+        _ => None,
+    };
+
+    youtube_id
+        .filter(|id| !id.is_empty())
+        .map(|id| format!("https://www.youtube-nocookie.com/embed/{id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_embeds() {
+        let config = Config {
+            embed_youtube: true,
+            peertube_instances: vec!["video.example".to_string()],
+            ..Default::default()
+        };
+        let embed = |link| embed_url(link, &config);
+
+        assert_eq!(
+            Some("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ".to_string()),
+            embed("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=10")
+        );
+        assert_eq!(
+            Some("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ".to_string()),
+            embed("https://youtu.be/dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            Some(
+                "https://video.example/videos/embed/9c9de5e8-0a1e-484a-b099-e80766180a6d"
+                    .to_string()
+            ),
+            embed("https://video.example/videos/watch/9c9de5e8-0a1e-484a-b099-e80766180a6d")
+        );
+        assert_eq!(None, embed("https://en.wikipedia.org/w/index.php"));
+        assert_eq!(None, embed("https://www.youtube.com/feed"));
+
+        // peertube-like links are only embedded from the configured instances
+        assert_eq!(
+            None,
+            embed("https://other.example/w/kkGMgK9ZtnKfYAgnEtQxbv")
+        );
+
+        // neither is embedded unless it's turned on
+        let config = Config::default();
+        assert_eq!(None, embed_url("https://youtu.be/dQw4w9WgXcQ", &config));
+        assert_eq!(
+            None,
+            embed_url("https://video.example/w/kkGMgK9ZtnKfYAgnEtQxbv", &config)
+        );
+    }
+
+    #[test]
+    fn only_embeds_links_alone_in_their_paragraph() {
+        let org = Org::parse(
+            "* post\nwatch [[https://youtu.be/a]] now\n\n[[https://youtu.be/b]]\n\n[[https://youtu.be/c][with a description]]\n",
+        );
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config {
+            embed_youtube: true,
+            ..Default::default()
+        };
+        let embeds = Embeds::collect(
+            &org,
+            &headline
+                .headline_node()
+                .descendants(org.arena())
+                .collect::<Vec<_>>(),
+            &config,
+        );
+
+        let links = headline
+            .headline_node()
+            .descendants(org.arena())
+            .filter_map(|node| embeds.link(&org[node]))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["https://www.youtube-nocookie.com/embed/b"], links);
+        assert_eq!(
+            1,
+            headline
+                .headline_node()
+                .descendants(org.arena())
+                .filter(|node| embeds.paragraph(&org[*node]))
+                .count()
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use color_eyre::{
+    eyre::{bail, Context, ContextCompat},
+    Result,
+};
+use sha1::{Digest, Sha1};
+use vfs::VfsPath;
+
+/// the program that runs source blocks of this language, if they can be executed
+///
+/// code is passed to it through stdin
+fn interpreter(language: &str) -> Option<&'static str> {
+    match language {
+        "sh" | "shell" => Some("sh"),
+        "bash" => Some("bash"),
+        "python" | "python3" => Some("python3"),
+        _ => None,
+    }
+}
+
+pub fn can_execute(language: &str) -> bool {
+    interpreter(language).is_some()
+}
+
+/// runs a source block from `folder` and returns its output
+///
+/// results are cached in `cache` by a hash of the language and the code, so blocks only run again when they change
+pub fn execute(language: &str, code: &str, folder: &Path, cache: &VfsPath) -> Result<String> {
+    let program = interpreter(language)
+        .with_context(|| format!("can't execute source blocks in '{language}'"))?;
+
+    let mut hasher = Sha1::new();
+    hasher.input(language.as_bytes());
+    hasher.input([0]);
+    hasher.input(code.as_bytes());
+    let cached = cache.join("exec")?.join(format!("{:x}", hasher.result()))?;
+
+    if cached.exists()? {
+        return Ok(cached.read_to_string()?);
+    }
+
+    let mut command = Command::new(program);
+    // the root folder is empty when sorg runs on a file in the current directory
+    if !folder.as_os_str().is_empty() {
+        command.current_dir(folder);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run '{program}'"))?;
+    // the code is written from another thread, or a program that prints a lot before reading all of it would block
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let code = code.to_string();
+    let writer = thread::spawn(move || stdin.write_all(code.as_bytes()));
+    let output = child.wait_with_output()?;
+    // programs can exit without reading all of the code, which isn't an error
+    let _ = writer.join();
+
+    if !output.status.success() {
+        bail!(
+            "'{program}' exited with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let result = String::from_utf8_lossy(&output.stdout).to_string();
+    cached.parent().create_dir_all()?;
+    cached.create_file()?.write_all(result.as_bytes())?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use vfs::MemoryFS;
+
+    use super::*;
+
+    #[test]
+    fn runs_and_caches_results() -> Result<()> {
+        let cache = VfsPath::new(MemoryFS::new());
+
+        let result = execute("sh", "echo hello", Path::new("."), &cache)?;
+        assert_eq!("hello\n", result);
+        assert_eq!(1, cache.join("exec")?.read_dir()?.count());
+
+        // the cached result is used instead of running the block again
+        let file = cache.join("exec")?.read_dir()?.next().unwrap();
+        file.create_file()?.write_all(b"cached\n")?;
+        let result = execute("sh", "echo hello", Path::new("."), &cache)?;
+        assert_eq!("cached\n", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn runs_bash_with_large_input_and_output() -> Result<()> {
+        let cache = VfsPath::new(MemoryFS::new());
+
+        // arrays are a bash feature that plain sh doesn't have
+        let result = execute("bash", "a=(1 2 3); echo ${#a[@]}", Path::new("."), &cache)?;
+        assert_eq!("3\n", result);
+
+        // output that fills the pipe before all of the code has been read
+        let code = format!("yes | head -c 1000000\n#{}", "x".repeat(1_000_000));
+        let result = execute("sh", &code, Path::new("."), &cache)?;
+        assert_eq!(1_000_000, result.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_errors() {
+        let cache = VfsPath::new(MemoryFS::new());
+
+        assert!(execute("sh", "exit 1", Path::new("."), &cache).is_err());
+        assert!(execute("ruby", "puts 1", Path::new("."), &cache).is_err());
+    }
+}
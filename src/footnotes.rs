@@ -1,68 +1,281 @@
+use std::collections::HashMap;
+
+use color_eyre::{eyre::bail, Report, Result};
 use orgize::{
     elements::{FnDef, FnRef},
-    indextree::NodeEdge,
-    Element, Event, Headline, Org,
+    export::HtmlHandler,
+    indextree::NodeId,
+    Element, Headline, Org,
 };
 use serde_derive::Serialize;
 
-#[derive(Serialize)]
+use crate::render::write_children_html;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct Footnote {
-    label: String,
-    definition: String,
+    pub label: String,
+    /// position of the first reference to this footnote
+    pub number: usize,
+    /// the definition, rendered to html
+    pub definition: String,
+    /// ids of every reference to this footnote, to link back to them
+    pub backrefs: Vec<String>,
+}
+
+/// label for anonymous footnotes like `[fn::definition]`, which are numbered in order
+pub fn anonymous_label(index: usize) -> String {
+    format!("anon-{index}")
+}
+
+/// id of the `n`th reference to a footnote, starting at 1
+pub fn reference_id(label: &str, n: usize) -> String {
+    if n == 1 {
+        format!("fnref-{label}")
+    } else {
+        format!("fnref-{label}-{n}")
+    }
 }
 
-pub fn get_footnotes(org: &Org<'_>, headline: &Headline) -> Vec<Footnote> {
-    let it = headline
+/// the definitions of the named footnotes referenced from `nodes`, in the order they are first referenced
+///
+/// definitions can be anywhere under the headline, like in a `Footnotes` subheadline
+pub fn referenced_definitions(org: &Org<'_>, headline: &Headline, nodes: &[NodeId]) -> Vec<NodeId> {
+    let definitions = headline
         .headline_node()
-        .traverse(org.arena())
-        .map(move |edge| match edge {
-            NodeEdge::Start(node) => Event::Start(&org[node]),
-            NodeEdge::End(node) => Event::End(&org[node]),
-        });
-
-    let mut footnotes = Vec::new();
-
-    let mut footnote_id = 0;
-    let mut in_footnote = None;
-
-    for event in it {
-        // println!("sub: {in_subheadline}, head: {in_headline}, title: {in_page_title}");
-        match event {
-            Event::Start(element) => match element {
-                Element::FnDef(FnDef { label, .. }) => {
-                    in_footnote = Some((label.to_string(), "".to_string()));
-                }
-                Element::FnRef(FnRef {
-                    label,
-                    definition: Some(def),
-                }) => {
-                    let label = if label.is_empty() {
-                        footnote_id += 1;
-                        footnote_id.to_string()
-                    } else {
-                        label.to_string()
-                    };
-                    footnotes.push(Footnote {
-                        label,
-                        definition: def.to_string(),
-                    });
+        .descendants(org.arena())
+        .filter_map(|node| match &org[node] {
+            Element::FnDef(FnDef { label, .. }) => Some((label.as_ref(), node)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut referenced = Vec::new();
+    for node in nodes {
+        if let Element::FnRef(FnRef { label, .. }) = &org[*node] {
+            if let Some(definition) = definitions.get(label.as_ref()) {
+                if !referenced.contains(definition) {
+                    referenced.push(*definition);
                 }
-                Element::Text { value } if in_footnote.is_some() => {
-                    if let Some((_, def)) = &mut in_footnote {
-                        def.push_str(value);
-                    }
+            }
+        }
+    }
+    referenced
+}
+
+/// the footnotes of a page, numbered in the order they are first referenced
+#[derive(Debug, Default)]
+pub struct Footnotes {
+    footnotes: Vec<Footnote>,
+}
+
+impl Footnotes {
+    /// collects the footnotes referenced from the `nodes` of a page that get rendered,
+    /// rendering their definitions with handlers from `handler`
+    ///
+    /// definitions can be anywhere under the headline, like in a `Footnotes` subheadline.
+    /// definitions that are never referenced are dropped, like org does, and references without one are an error
+    pub fn collect<H: HtmlHandler<Report>>(
+        org: &Org<'_>,
+        headline: &Headline,
+        nodes: &[NodeId],
+        handler: impl Fn() -> H,
+    ) -> Result<Self> {
+        let mut footnotes: Vec<Footnote> = Vec::new();
+        let mut inline_definitions = HashMap::new();
+        let mut anonymous = 0;
+
+        for node in nodes {
+            let Element::FnRef(FnRef { label, definition }) = &org[*node] else {
+                continue;
+            };
+            let label = if label.is_empty() {
+                anonymous += 1;
+                anonymous_label(anonymous)
+            } else {
+                label.to_string()
+            };
+            if let Some(definition) = definition {
+                inline_definitions.insert(label.clone(), definition.to_string());
+            }
+
+            match footnotes.iter_mut().find(|f| f.label == label) {
+                Some(footnote) => {
+                    let n = footnote.backrefs.len() + 1;
+                    footnote.backrefs.push(reference_id(&label, n));
                 }
-                _ => {}
-            },
-            Event::End(element) => {
-                if let Element::FnDef(_) = element {
-                    if let Some((label, definition)) = in_footnote.take() {
-                        footnotes.push(Footnote { label, definition });
-                    }
+                None => footnotes.push(Footnote {
+                    backrefs: vec![reference_id(&label, 1)],
+                    number: footnotes.len() + 1,
+                    definition: String::new(),
+                    label,
+                }),
+            }
+        }
+
+        let definitions = headline
+            .headline_node()
+            .descendants(org.arena())
+            .filter_map(|node| match &org[node] {
+                Element::FnDef(FnDef { label, .. }) => Some((label.to_string(), node)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        for footnote in &mut footnotes {
+            if let Some(node) = definitions.get(&footnote.label) {
+                footnote.definition = write_children_html(*node, org, handler())?;
+            } else if let Some(definition) = inline_definitions.get(&footnote.label) {
+                let inline = Org::parse(definition);
+                if let Some(section) = inline.document().section_node() {
+                    footnote.definition = write_children_html(section, &inline, handler())?;
                 }
+            } else {
+                bail!(
+                    "footnote '{}' is referenced but never defined",
+                    footnote.label
+                );
             }
         }
+
+        Ok(Self { footnotes })
+    }
+
+    pub fn get(&self, label: &str) -> Option<&Footnote> {
+        self.footnotes.iter().find(|f| f.label == label)
+    }
+
+    pub fn list(&self) -> &[Footnote] {
+        &self.footnotes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        render::{rendered_nodes, CommonHtmlHandler},
+    };
+
+    use super::*;
+
+    #[test]
+    fn numbers_footnotes_by_first_reference() -> Result<()> {
+        let source = r#"
+* post
+first[fn:b], anonymous[fn:: with /emphasis/], and again[fn:a][fn:b]
+
+[fn:a] a [[https://example.com][link]] and ~code~
+[fn:b] plain
+[fn:unused] never referenced
+"#;
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config {
+            url: "http://localhost".to_string(),
+            ..Default::default()
+        };
+        let footnotes = Footnotes::collect(
+            &org,
+            &headline,
+            &rendered_nodes(&org, &headline, false, &config),
+            || CommonHtmlHandler::new(&config, Default::default(), "/post"),
+        )?;
+
+        let labels = footnotes
+            .list()
+            .iter()
+            .map(|f| (f.label.as_str(), f.number))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("b", 1), ("anon-1", 2), ("a", 3)], labels);
+
+        let b = footnotes.get("b").unwrap();
+        assert_eq!(vec!["fnref-b", "fnref-b-2"], b.backrefs);
+        assert_eq!("<p > plain</p>", b.definition);
+
+        let a = footnotes.get("a").unwrap();
+        assert!(a.definition.contains(r#"<a href="https://example.com/""#));
+        assert!(a.definition.contains("<code>code</code>"));
+
+        let anonymous = footnotes.get("anon-1").unwrap();
+        assert!(anonymous.definition.contains("<i >emphasis</i>"));
+
+        Ok(())
     }
 
-    footnotes
+    #[test]
+    fn only_collects_rendered_references() -> Result<()> {
+        let source = r#"
+* index
+see[fn:a]
+** child post
+not on the index[fn:b]
+** Footnotes
+[fn:a] defined in a subheadline
+[fn:b] the child's
+"#;
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config::default();
+        let collect = |index| {
+            Footnotes::collect(
+                &org,
+                &headline,
+                &rendered_nodes(&org, &headline, index, &config),
+                || CommonHtmlHandler::new(&config, Default::default(), "/"),
+            )
+        };
+
+        let labels = |footnotes: Footnotes| {
+            footnotes
+                .list()
+                .iter()
+                .map(|f| f.label.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["a"], labels(collect(true)?));
+        assert_eq!(vec!["a", "b"], labels(collect(false)?));
+
+        let org = Org::parse(
+            "* post
+missing[fn:nope]
+",
+        );
+        let headline = org.document().first_child(&org).unwrap();
+        assert!(Footnotes::collect(
+            &org,
+            &headline,
+            &rendered_nodes(&org, &headline, false, &config),
+            || CommonHtmlHandler::new(&config, Default::default(), "/post"),
+        )
+        .is_err());
+
+        // references in hidden results aren't rendered, so they don't take a number
+        let org = Org::parse(
+            "* post
+#+begin_src sh
+echo hi
+#+end_src
+
+#+RESULTS:
+hidden[fn:: hidden]
+
+visible[fn:: visible]
+",
+        );
+        let headline = org.document().first_child(&org).unwrap();
+        let footnotes = Footnotes::collect(
+            &org,
+            &headline,
+            &rendered_nodes(&org, &headline, false, &config),
+            || CommonHtmlHandler::new(&config, Default::default(), "/post"),
+        )?;
+        assert_eq!(1, footnotes.list().len());
+        assert!(footnotes
+            .get("anon-1")
+            .unwrap()
+            .definition
+            .contains("visible"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,143 @@
+use std::io::Read;
+
+use color_eyre::{eyre::Context, Result};
+use serde_derive::Serialize;
+
+use crate::{
+    images::{display_size, Resizer},
+    Config,
+};
+
+/// an image in a gallery, see [`get_gallery`]
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GalleryImage {
+    pub src: String,
+    pub thumb: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub caption: Option<String>,
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp"];
+
+/// lists the images in a folder inside of static, sorted by filename, and makes their thumbnails
+///
+/// captions are read from a sidecar file with the same name and a `.txt` extension, like `beach.txt` for `beach.jpg`.
+/// images that are already small enough, or can't be resized like gifs, are their own thumbnail
+pub fn get_gallery(config: &Config, folder: &str) -> Result<Vec<GalleryImage>> {
+    let folder = folder.trim_matches('/');
+    let path = config.static_path.join(folder)?;
+    let mut files = path
+        .read_dir()
+        .with_context(|| format!("gallery folder '{folder}' doesn't exist in static"))?
+        .filter(|file| {
+            file.extension()
+                .map(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|file| file.filename());
+
+    let base_url = url::Url::parse(&config.url)?;
+
+    files
+        .into_iter()
+        .map(|file| {
+            let filename = file.filename();
+
+            let mut data = Vec::new();
+            file.open_file()?.read_to_end(&mut data)?;
+            let size = display_size(&data);
+
+            let stem = filename
+                .rsplit_once('.')
+                .map_or(&*filename, |(stem, _)| stem);
+            let sidecar = path.join(format!("{stem}.txt"))?;
+            let caption = if sidecar.exists()? {
+                Some(sidecar.read_to_string()?.trim().to_string())
+            } else {
+                None
+            };
+
+            let src = format!("{folder}/{filename}");
+            let thumb = match size {
+                Some((width, _)) if width > config.gallery_thumbnail_width => {
+                    Resizer::new(&data, &src).resize(
+                        config.gallery_thumbnail_width,
+                        &config.build_path,
+                        &config.cache_path,
+                    )?
+                }
+                _ => None,
+            };
+
+            Ok(GalleryImage {
+                thumb: base_url.join(thumb.as_ref().unwrap_or(&src))?.to_string(),
+                src: base_url.join(&src)?.to_string(),
+                width: size.map(|s| s.0),
+                height: size.map(|s| s.1),
+                caption,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use image::{ImageFormat, RgbImage};
+    use vfs::VfsPath;
+
+    use super::*;
+
+    fn write_image(file: &VfsPath, width: u32, format: ImageFormat) -> Result<()> {
+        let mut data = Vec::new();
+        RgbImage::new(width, width / 2).write_to(&mut Cursor::new(&mut data), format)?;
+        file.create_file()?.write_all(&data)?;
+        Ok(())
+    }
+
+    #[test]
+    fn lists_images() -> Result<()> {
+        let config = Config {
+            url: "http://localhost".to_string(),
+            gallery_thumbnail_width: 100,
+            ..Default::default()
+        };
+        let folder = config.static_path.join("photos/trip")?;
+        folder.create_dir_all()?;
+        write_image(&folder.join("a.jpg")?, 800, ImageFormat::Jpeg)?;
+        write_image(&folder.join("b.png")?, 400, ImageFormat::Png)?;
+        folder
+            .join("a.txt")?
+            .create_file()?
+            .write_all(b"the beach\n")?;
+
+        let gallery = get_gallery(&config, "photos/trip")?;
+        assert_eq!(
+            vec![
+                GalleryImage {
+                    src: "http://localhost/photos/trip/a.jpg".to_string(),
+                    thumb: "http://localhost/photos/trip/a-100w.jpg".to_string(),
+                    width: Some(800),
+                    height: Some(400),
+                    caption: Some("the beach".to_string()),
+                },
+                GalleryImage {
+                    src: "http://localhost/photos/trip/b.png".to_string(),
+                    thumb: "http://localhost/photos/trip/b-100w.webp".to_string(),
+                    width: Some(400),
+                    height: Some(200),
+                    caption: None,
+                },
+            ],
+            gallery
+        );
+        assert!(config.build_path.join("photos/trip/a-100w.jpg")?.exists()?);
+
+        assert!(get_gallery(&config, "photos/missing").is_err());
+
+        Ok(())
+    }
+}
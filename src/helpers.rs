@@ -1,5 +1,17 @@
 use orgize::{Element, Event, Org};
 
+/// identifies an element of a parsed org file by its address, since handlers don't get node ids
+///
+/// the arena isn't modified while it's being rendered, so elements stay where they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ElementKey(usize);
+
+impl ElementKey {
+    pub fn of(element: &Element) -> Self {
+        Self(element as *const Element as usize)
+    }
+}
+
 /// extracts a file out of an org link
 ///
 /// "[[file:test.org][linked blogpost]]" -> "test.org"
@@ -25,6 +37,59 @@ pub fn parse_file_link(link: &str) -> Option<String> {
     None
 }
 
+/// parses an org property list, like the ones used in `#+ATTR_HTML`
+///
+/// `:class wide :title "a title" :style color:red;` -> [("class", "wide"), ("title", "a title"), ("style", "color:red;")]
+///
+/// values run until the next `:key`, and can be quoted to keep spaces or leading colons.
+/// keys without a value get an empty string
+pub fn parse_plist(value: &str) -> Vec<(String, String)> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(c) = chars.next() else {
+            break;
+        };
+
+        let (token, quoted) = if c == '"' {
+            let mut token = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => token.extend(chars.next()),
+                    c => token.push(c),
+                }
+            }
+            (token, true)
+        } else {
+            let mut token = c.to_string();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+            (token, false)
+        };
+
+        match token.strip_prefix(':') {
+            Some(key) if !quoted && !key.is_empty() => {
+                pairs.push((key.to_string(), String::new()));
+            }
+            _ => {
+                // values before the first key are ignored
+                if let Some((_, v)) = pairs.last_mut() {
+                    if !v.is_empty() {
+                        v.push(' ');
+                    }
+                    v.push_str(&token);
+                }
+            }
+        }
+    }
+
+    pairs
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -34,4 +99,31 @@ mod tests {
         let s = parse_file_link("[[file:test.org][linked blogpost]]");
         assert_eq!(s, Some("test.org".to_string()));
     }
+
+    #[test]
+    fn test_parse_plist() {
+        let pairs = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            parse_plist(":style color:red;"),
+            pairs(&[("style", "color:red;")])
+        );
+        assert_eq!(
+            parse_plist(r#":class wide table :id "my id" :title "say \"hi\"""#),
+            pairs(&[
+                ("class", "wide table"),
+                ("id", "my id"),
+                ("title", "say \"hi\""),
+            ])
+        );
+        assert_eq!(
+            parse_plist(":controls :data-x \":not-a-key\""),
+            pairs(&[("controls", ""), ("data-x", ":not-a-key")])
+        );
+        assert_eq!(parse_plist("no key here"), pairs(&[]));
+    }
 }
@@ -0,0 +1,125 @@
+use std::io::{Cursor, Write};
+
+use color_eyre::{eyre::WrapErr, Result};
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageReader,
+};
+use sha1::{Digest, Sha1};
+use vfs::VfsPath;
+
+/// quality of resized jpeg images
+const JPEG_QUALITY: u8 = 80;
+
+/// the dimensions of an image as it's shown, which for jpeg photos depends on their orientation
+///
+/// only the header of the image is read, and `None` is returned for formats that can't be read
+pub fn display_size(data: &[u8]) -> Option<(u32, u32)> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+    match decoder.orientation().unwrap_or(Orientation::NoTransforms) {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => Some((height, width)),
+        _ => Some((width, height)),
+    }
+}
+
+/// makes resized copies of an image, next to it in the build folder
+///
+/// jpeg photos stay jpeg, and png and webp images become lossless webp, which keeps their transparency.
+/// other formats, like animated gifs, aren't resized.
+/// copies are cached by the hash of the image, so they're only made again when it changes
+pub struct Resizer<'a> {
+    data: &'a [u8],
+    /// of the image, relative to the build folder
+    path: &'a str,
+    hash: String,
+    image: Option<DynamicImage>,
+}
+
+impl<'a> Resizer<'a> {
+    pub fn new(data: &'a [u8], path: &'a str) -> Self {
+        let mut hasher = Sha1::new();
+        hasher.input(data);
+        Self {
+            data,
+            path,
+            hash: format!("{:x}", hasher.result()),
+            image: None,
+        }
+    }
+
+    /// writes a copy of the image that is `width` pixels wide, and returns its path relative to the build folder
+    ///
+    /// returns `None` if the image can't be resized
+    pub fn resize(
+        &mut self,
+        width: u32,
+        build: &VfsPath,
+        cache: &VfsPath,
+    ) -> Result<Option<String>> {
+        let extension = match image::guess_format(self.data) {
+            Ok(image::ImageFormat::Jpeg) => "jpg",
+            Ok(image::ImageFormat::Png | image::ImageFormat::WebP) => "webp",
+            _ => return Ok(None),
+        };
+        let stem = self
+            .path
+            .rsplit_once('.')
+            .map_or(self.path, |(stem, _)| stem);
+        let path = format!("{stem}-{width}w.{extension}");
+
+        let cached = cache
+            .join("images")?
+            .join(format!("{}-{width}.{extension}", self.hash))?;
+        if !cached.exists()? {
+            let resized = self.image()?.resize(width, u32::MAX, FilterType::Lanczos3);
+            let mut out = Cursor::new(Vec::new());
+            if extension == "jpg" {
+                resized
+                    .to_rgb8()
+                    .write_with_encoder(JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY))?;
+            } else {
+                resized
+                    .to_rgba8()
+                    .write_with_encoder(WebPEncoder::new_lossless(&mut out))?;
+            }
+
+            cached.parent().create_dir_all()?;
+            cached.create_file()?.write_all(out.get_ref())?;
+        }
+
+        // images can be used more than once in a build
+        let file = build.join(path.trim_start_matches('/'))?;
+        if !file.exists()? {
+            file.parent().create_dir_all()?;
+            cached.copy_file(&file)?;
+        }
+
+        Ok(Some(path))
+    }
+
+    /// the decoded image, turned the way it's shown
+    fn image(&mut self) -> Result<&DynamicImage> {
+        if self.image.is_none() {
+            let mut decoder = ImageReader::new(Cursor::new(self.data))
+                .with_guessed_format()?
+                .into_decoder()
+                .wrap_err_with(|| format!("failed to read image '{}'", self.path))?;
+            let orientation = decoder.orientation()?;
+            let mut image = DynamicImage::from_decoder(decoder)
+                .wrap_err_with(|| format!("failed to decode image '{}'", self.path))?;
+            image.apply_orientation(orientation);
+            self.image = Some(image);
+        }
+        Ok(self.image.as_ref().expect("the image was just decoded"))
+    }
+}
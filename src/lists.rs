@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+
+use orgize::{indextree::NodeId, Element, Headline, Org};
+
+use crate::helpers::ElementKey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    Unordered,
+    Ordered,
+    /// items like `- term :: definition`, decided by the first item
+    Description,
+}
+
+/// a checkbox at the start of a list item, like `[X]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checkbox {
+    Checked,
+    Unchecked,
+    /// `[-]`, for items with some of their children checked
+    Partial,
+}
+
+impl Checkbox {
+    /// class of the item, the same ones org uses
+    pub fn class(self) -> &'static str {
+        match self {
+            Checkbox::Checked => "on",
+            Checkbox::Unchecked => "off",
+            Checkbox::Partial => "trans",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ItemInfo {
+    pub checkbox: Option<Checkbox>,
+    /// set with `[@3]`, only in ordered lists
+    pub counter: Option<u32>,
+    pub description: bool,
+}
+
+/// what to do with the text at the start of an item, which is where orgize leaves checkboxes, counters and terms
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TextInfo {
+    /// length of the counter and checkbox at the start, which aren't written
+    pub skip: usize,
+    pub checkbox: Option<Checkbox>,
+    /// where the term of a description item ends, and where its definition starts
+    pub term: Option<(usize, usize)>,
+}
+
+/// what orgize doesn't parse about the lists of a page
+#[derive(Debug, Default)]
+pub struct Lists {
+    lists: HashMap<ElementKey, ListKind>,
+    items: HashMap<ElementKey, ItemInfo>,
+    texts: HashMap<ElementKey, TextInfo>,
+    /// progress cookies like `[1/3]` in list items, with their values filled in
+    cookies: HashMap<ElementKey, String>,
+}
+
+impl Lists {
+    pub fn collect(org: &Org<'_>, headline: &Headline) -> Self {
+        let mut lists = Self::default();
+
+        for node in headline.headline_node().descendants(org.arena()) {
+            match &org[node] {
+                Element::List(list) => lists.add_list(org, node, list.ordered),
+                Element::Cookie(cookie) => {
+                    if let Some(progress) = item_progress(org, node, &cookie.value) {
+                        lists.cookies.insert(ElementKey::of(&org[node]), progress);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        lists
+    }
+
+    fn add_list(&mut self, org: &Org<'_>, list: NodeId, ordered: bool) {
+        let items = list
+            .children(org.arena())
+            .filter(|item| matches!(org[*item], Element::ListItem(_)))
+            .map(|item| (item, ItemPrefix::parse(org, item)))
+            .collect::<Vec<_>>();
+
+        let kind = match items.first() {
+            _ if ordered => ListKind::Ordered,
+            Some((item, prefix)) if find_term(org, *item, prefix.skip).is_some() => {
+                ListKind::Description
+            }
+            _ => ListKind::Unordered,
+        };
+        self.lists.insert(ElementKey::of(&org[list]), kind);
+
+        for (item, prefix) in items {
+            let description = kind == ListKind::Description;
+            self.items.insert(
+                ElementKey::of(&org[item]),
+                ItemInfo {
+                    checkbox: prefix.checkbox,
+                    counter: prefix.counter.filter(|_| kind == ListKind::Ordered),
+                    description,
+                },
+            );
+
+            if let Some(text) = prefix.text {
+                self.texts.insert(
+                    ElementKey::of(&org[text]),
+                    TextInfo {
+                        skip: prefix.skip,
+                        checkbox: prefix.checkbox,
+                        term: None,
+                    },
+                );
+            }
+            if description {
+                if let Some((text, term)) = find_term(org, item, prefix.skip) {
+                    self.texts
+                        .entry(ElementKey::of(&org[text]))
+                        .or_default()
+                        .term = Some(term);
+                }
+            }
+        }
+    }
+
+    pub fn list(&self, element: &Element) -> Option<ListKind> {
+        self.lists.get(&ElementKey::of(element)).copied()
+    }
+
+    pub fn item(&self, element: &Element) -> Option<&ItemInfo> {
+        self.items.get(&ElementKey::of(element))
+    }
+
+    pub fn text(&self, element: &Element) -> Option<&TextInfo> {
+        self.texts.get(&ElementKey::of(element))
+    }
+
+    pub fn cookie(&self, element: &Element) -> Option<&str> {
+        self.cookies
+            .get(&ElementKey::of(element))
+            .map(String::as_str)
+    }
+}
+
+/// the counter and checkbox at the start of an item, like `[@3] [X] `
+#[derive(Debug, Default)]
+struct ItemPrefix {
+    /// the first text of the item, which they are in
+    text: Option<NodeId>,
+    skip: usize,
+    counter: Option<u32>,
+    checkbox: Option<Checkbox>,
+}
+
+impl ItemPrefix {
+    fn parse(org: &Org<'_>, item: NodeId) -> Self {
+        let Some((text, value)) = first_text(org, item) else {
+            return Self::default();
+        };
+
+        let mut prefix = Self {
+            text: Some(text),
+            ..Default::default()
+        };
+        let mut rest = value;
+
+        if let Some((counter, after)) = rest
+            .strip_prefix("[@")
+            .and_then(|r| r.split_once(']'))
+            .and_then(|(n, after)| Some((n.parse().ok()?, after)))
+        {
+            prefix.counter = Some(counter);
+            rest = after.trim_start_matches(' ');
+        }
+
+        let checkbox = match rest.get(..3) {
+            Some("[X]" | "[x]") => Some(Checkbox::Checked),
+            Some("[ ]") => Some(Checkbox::Unchecked),
+            Some("[-]") => Some(Checkbox::Partial),
+            _ => None,
+        };
+        if checkbox.is_some() && rest[3..].chars().next().is_none_or(char::is_whitespace) {
+            prefix.checkbox = checkbox;
+            rest = rest[3..].trim_start_matches(' ');
+        }
+
+        prefix.skip = value.len() - rest.len();
+        prefix
+    }
+}
+
+/// the text the first paragraph of an item starts with
+fn first_text<'a>(org: &'a Org<'_>, item: NodeId) -> Option<(NodeId, &'a str)> {
+    let paragraph = item.children(org.arena()).next()?;
+    if !matches!(org[paragraph], Element::Paragraph { .. }) {
+        return None;
+    }
+    let text = paragraph.children(org.arena()).next()?;
+    match &org[text] {
+        Element::Text { value } => Some((text, value)),
+        _ => None,
+    }
+}
+
+/// finds the ` :: ` that ends the term of a description item, which can come after some markup
+///
+/// returns the text it's in, where the term ends and where the definition starts
+fn find_term(org: &Org<'_>, item: NodeId, skip: usize) -> Option<(NodeId, (usize, usize))> {
+    let paragraph = item.children(org.arena()).next()?;
+    if !matches!(org[paragraph], Element::Paragraph { .. }) {
+        return None;
+    }
+
+    for (i, text) in paragraph.children(org.arena()).enumerate() {
+        let Element::Text { value } = &org[text] else {
+            continue;
+        };
+        let start = if i == 0 { skip } else { 0 };
+
+        let mut search = start;
+        while let Some(found) = value[search..].find("::") {
+            let at = search + found;
+            let before = value[start..at].chars().next_back();
+            let after = value[at + 2..].chars().next();
+            // the term can't be empty, and the separator needs whitespace around it
+            if (before.is_some_and(char::is_whitespace) || (at == start && i > 0))
+                && after.is_none_or(char::is_whitespace)
+            {
+                let end = value[..at].trim_end().len().max(start);
+                let definition =
+                    at + 2 + (value[at + 2..].len() - value[at + 2..].trim_start().len());
+                return Some((text, (end, definition)));
+            }
+            search = at + 2;
+        }
+    }
+    None
+}
+
+/// fills in a progress cookie like `[/]` or `[%]` from the checkboxes of the item's children
+fn item_progress(org: &Org<'_>, cookie: NodeId, value: &str) -> Option<String> {
+    let paragraph = cookie.ancestors(org.arena()).nth(1)?;
+    let item = paragraph.ancestors(org.arena()).nth(1)?;
+    if !matches!(org[item], Element::ListItem(_)) {
+        return None;
+    }
+
+    let checkboxes = item
+        .children(org.arena())
+        .filter(|child| matches!(org[*child], Element::List(_)))
+        .flat_map(|list| list.children(org.arena()))
+        .filter_map(|child| ItemPrefix::parse(org, child).checkbox)
+        .collect::<Vec<_>>();
+    let done = checkboxes
+        .iter()
+        .filter(|c| **c == Checkbox::Checked)
+        .count();
+    let total = checkboxes.len();
+
+    Some(if value.contains('%') {
+        format!("[{}%]", (done * 100).checked_div(total).unwrap_or(0))
+    } else {
+        format!("[{done}/{total}]")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the elements under the first headline, in order
+    fn elements<'a>(org: &'a Org<'_>) -> Vec<&'a Element<'a>> {
+        let headline = org.document().first_child(org).unwrap();
+        headline
+            .headline_node()
+            .descendants(org.arena())
+            .map(|node| &org[node])
+            .collect()
+    }
+
+    #[test]
+    fn parses_checkboxes_and_counters() {
+        let org = Org::parse("* a\n1. [@3] [X] third\n2. [ ] fourth\n3. [-]partial\n");
+        let headline = org.document().first_child(&org).unwrap();
+        let lists = Lists::collect(&org, &headline);
+
+        assert_eq!(
+            vec![
+                &ItemInfo {
+                    checkbox: Some(Checkbox::Checked),
+                    counter: Some(3),
+                    description: false
+                },
+                &ItemInfo {
+                    checkbox: Some(Checkbox::Unchecked),
+                    counter: None,
+                    description: false
+                },
+                // a checkbox has to be followed by whitespace
+                &ItemInfo::default(),
+            ],
+            elements(&org)
+                .into_iter()
+                .filter_map(|element| lists.item(element))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn finds_description_terms() {
+        let org = Org::parse("* a\n- ~term~ :: definition\n- other ::\n  defined\n- no term\n");
+        let headline = org.document().first_child(&org).unwrap();
+        let lists = Lists::collect(&org, &headline);
+
+        let terms = elements(&org)
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Text { value } => lists
+                    .text(element)
+                    .and_then(|info| info.term)
+                    .map(|(end, definition)| (&value[..end], &value[definition..])),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("", "definition"), ("other", "defined")], terms);
+    }
+}
@@ -43,7 +43,7 @@ impl Macros {
         for ev in org.iter() {
             match ev {
                 orgize::Event::Start(element) => match element {
-                    Element::SpecialBlock(block) => {
+                    Element::SpecialBlock(block) if block.name.eq_ignore_ascii_case("macro") => {
                         if let Some(parameters) = &block.parameters {
                             let mut parameters = parameters.split_whitespace();
 
@@ -185,6 +185,19 @@ this is a second line
         );
     }
 
+    #[test]
+    fn ignores_other_special_blocks() {
+        let source = "#+BEGIN_DETAILS Summary text
+some details
+#+END_DETAILS";
+
+        let org = Org::parse(source);
+
+        let macros = Macros::parse(&org).unwrap();
+
+        assert!(macros.macros.is_empty());
+    }
+
     #[test]
     fn to_tera_macro() {
         let m = Macro {
@@ -11,18 +11,34 @@ use std::{path::Path, rc::Rc, time::Duration};
 use vfs::{PhysicalFS, VfsPath};
 
 mod args;
+mod bibliography;
+mod citations;
 mod config;
 mod context;
 mod count_words;
+mod crossrefs;
+mod dot;
+mod embeds;
+mod execute;
 mod folders;
 mod footnotes;
+mod gallery;
 mod helpers;
 mod hotreloading;
+mod images;
+mod lists;
 mod macros;
+mod math;
 mod page;
 mod render;
 mod rss;
+mod source_block;
+mod syntaxes;
+mod table;
+mod tangle;
 mod tera;
+mod timestamps;
+mod typography;
 
 use crate::tera::make_tera;
 use args::{Args, SorgMode};
@@ -145,6 +161,10 @@ fn build_files(config: &Config, org: Org<'_>, mut tera: Tera) -> Result<()> {
         .copy_dir(&config.build_path)
         .with_context(|| "Failed to copy static folder into build folder")?;
 
+    if config.syntax_highlighting_classes {
+        render::write_syntax_stylesheets(config)?;
+    }
+
     tera.register_function("get_pages", tera::make_get_pages(&tree));
     tree.render(
         &tera,
@@ -0,0 +1,850 @@
+use std::fmt::Write;
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use orgize::export::HtmlEscape;
+
+/// a piece of text that may contain latex math fragments
+#[derive(Debug, PartialEq, Eq)]
+pub enum Fragment<'a> {
+    Text(&'a str),
+    Math { latex: &'a str, display: bool },
+}
+
+/// splits text into plain text and latex fragments
+///
+/// recognizes `\(..\)`, `$..$` as inline math, and `\[..\]`, `$$..$$` and `\begin{env}..\end{env}` as display math.
+/// openers without a matching closer are left as text
+pub fn split_fragments(text: &str) -> Vec<Fragment<'_>> {
+    let mut fragments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let found = if rest.starts_with("\\(") {
+            find_closing(text, i, 2, "\\)", false)
+        } else if rest.starts_with("\\[") {
+            find_closing(text, i, 2, "\\]", true)
+        } else if rest.starts_with("$$") {
+            find_closing(text, i, 2, "$$", true)
+        } else if rest.starts_with('$') {
+            find_inline_dollar(text, i)
+        } else if rest.starts_with("\\begin{") {
+            find_environment(text, i)
+        } else {
+            None
+        };
+
+        match found {
+            Some((end, fragment)) => {
+                if start < i {
+                    fragments.push(Fragment::Text(&text[start..i]));
+                }
+                fragments.push(fragment);
+                start = end;
+                i = end;
+            }
+            None => i += rest.chars().next().map(char::len_utf8).unwrap_or(1),
+        }
+    }
+
+    if start < text.len() {
+        fragments.push(Fragment::Text(&text[start..]));
+    }
+
+    fragments
+}
+
+/// the equations in latex that only contains display math, like the contents of a `#+begin_export latex` block
+pub fn display_math(latex: &str) -> Option<Vec<&str>> {
+    let mut equations = Vec::new();
+    for fragment in split_fragments(latex) {
+        match fragment {
+            Fragment::Math {
+                latex,
+                display: true,
+            } => equations.push(latex),
+            Fragment::Text(text) if text.trim().is_empty() => {}
+            _ => return None,
+        }
+    }
+    (!equations.is_empty()).then_some(equations)
+}
+
+fn find_closing<'a>(
+    text: &'a str,
+    i: usize,
+    open_len: usize,
+    close: &str,
+    display: bool,
+) -> Option<(usize, Fragment<'a>)> {
+    let body_start = i + open_len;
+    let len = text[body_start..].find(close)?;
+    Some((
+        body_start + len + close.len(),
+        Fragment::Math {
+            latex: &text[body_start..body_start + len],
+            display,
+        },
+    ))
+}
+
+/// `$..$` follows org's rules, so prices like "$5 and $10" aren't treated as math
+///
+/// there can't be whitespace just inside the dollars, the closing one has to be followed by whitespace,
+/// punctuation or the end of the text, and the math can span at most three lines
+fn find_inline_dollar(text: &str, i: usize) -> Option<(usize, Fragment<'_>)> {
+    let body_start = i + 1;
+    let first = text[body_start..].chars().next()?;
+    if first.is_whitespace() || ".,;$".contains(first) {
+        return None;
+    }
+
+    let len = text[body_start..].find('$')?;
+    let body = &text[body_start..body_start + len];
+    let last = body.chars().last()?;
+    if last.is_whitespace() || ".,\\".contains(last) || body.matches('\n').count() > 2 {
+        return None;
+    }
+
+    let end = body_start + len + 1;
+    if let Some(after) = text[end..].chars().next() {
+        if !(after.is_whitespace() || after.is_ascii_punctuation()) {
+            return None;
+        }
+    }
+
+    Some((
+        end,
+        Fragment::Math {
+            latex: body,
+            display: false,
+        },
+    ))
+}
+
+fn find_environment(text: &str, i: usize) -> Option<(usize, Fragment<'_>)> {
+    let name_start = i + "\\begin{".len();
+    let name_len = text[name_start..].find('}')?;
+    let close = format!("\\end{{{}}}", &text[name_start..name_start + name_len]);
+    let len = text[i..].find(&close)?;
+    let end = i + len + close.len();
+
+    Some((
+        end,
+        Fragment::Math {
+            latex: &text[i..end],
+            display: true,
+        },
+    ))
+}
+
+/// converts a latex math expression into a `<math>` element
+///
+/// this only covers what posts use: greek letters and common symbols, scripts, `\frac`, `\sqrt`, `\left`/`\right`,
+/// `\text`, a few fonts and accents, and the `equation`, `align`, `cases` and matrix environments.
+/// anything else is an error, unlike crates like `latex2mathml` which don't support `align` or `cases`
+/// and write unknown commands into the output
+pub fn latex_to_mathml(latex: &str, display: bool) -> Result<String> {
+    let tokens = tokenize(latex)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        variant: None,
+    };
+
+    let row = parser.parse_row()?;
+    if let Some(token) = parser.peek() {
+        bail!("unexpected {token:?}");
+    }
+
+    Ok(format!(
+        "<math{}><mrow>{row}</mrow></math>",
+        if display { " display=\"block\"" } else { "" }
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Command(String),
+    /// commands that take raw text, like `\text{..}`
+    Text(String, String),
+    Number(String),
+    Char(char),
+    Open,
+    Close,
+    Sub,
+    Sup,
+    Align,
+    Newline,
+    Space(&'static str),
+}
+
+const TEXT_COMMANDS: &[&str] = &["text", "textit", "textbf", "operatorname"];
+
+fn tokenize(latex: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = latex.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\\' => match chars.next() {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let mut name = c.to_string();
+                    while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+                        name.push(c);
+                    }
+
+                    if TEXT_COMMANDS.contains(&name.as_str()) {
+                        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                        if chars.next() != Some('{') {
+                            bail!("expected {{ after \\{name}");
+                        }
+                        let mut depth = 0;
+                        let mut text = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('{') => depth += 1,
+                                Some('}') if depth == 0 => break,
+                                Some('}') => depth -= 1,
+                                Some(c) => text.push(c),
+                                None => bail!("unclosed \\{name}"),
+                            }
+                        }
+                        Token::Text(name, text)
+                    } else {
+                        Token::Command(name)
+                    }
+                }
+                Some('\\') => Token::Newline,
+                Some(',') => Token::Space("0.1667em"),
+                Some(':') | Some('>') => Token::Space("0.2222em"),
+                Some(';') => Token::Space("0.2778em"),
+                Some(' ') => Token::Space("0.25em"),
+                Some('!') => continue,
+                Some(c) => Token::Char(c),
+                None => bail!("trailing \\"),
+            },
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '_' => Token::Sub,
+            '^' => Token::Sup,
+            '&' => Token::Align,
+            '~' => Token::Space("0.25em"),
+            c if c.is_whitespace() => continue,
+            c if c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                // a trailing dot is punctuation, not part of the number
+                if number.ends_with('.') {
+                    number.pop();
+                    tokens.push(Token::Number(number));
+                    Token::Char('.')
+                } else {
+                    Token::Number(number)
+                }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// how a symbol command is rendered
+#[derive(Clone, Copy)]
+enum Kind {
+    /// `<mi>`
+    Ident,
+    /// `<mo>`
+    Op,
+    /// `<mo>` that takes its scripts as limits in display mode
+    LargeOp,
+}
+
+const SYMBOLS: &[(&str, &str, Kind)] = &[
+    ("alpha", "α", Kind::Ident),
+    ("beta", "β", Kind::Ident),
+    ("gamma", "γ", Kind::Ident),
+    ("delta", "δ", Kind::Ident),
+    ("epsilon", "ϵ", Kind::Ident),
+    ("varepsilon", "ε", Kind::Ident),
+    ("zeta", "ζ", Kind::Ident),
+    ("eta", "η", Kind::Ident),
+    ("theta", "θ", Kind::Ident),
+    ("iota", "ι", Kind::Ident),
+    ("kappa", "κ", Kind::Ident),
+    ("lambda", "λ", Kind::Ident),
+    ("mu", "μ", Kind::Ident),
+    ("nu", "ν", Kind::Ident),
+    ("xi", "ξ", Kind::Ident),
+    ("pi", "π", Kind::Ident),
+    ("rho", "ρ", Kind::Ident),
+    ("sigma", "σ", Kind::Ident),
+    ("tau", "τ", Kind::Ident),
+    ("upsilon", "υ", Kind::Ident),
+    ("phi", "ϕ", Kind::Ident),
+    ("varphi", "φ", Kind::Ident),
+    ("chi", "χ", Kind::Ident),
+    ("psi", "ψ", Kind::Ident),
+    ("omega", "ω", Kind::Ident),
+    ("Gamma", "Γ", Kind::Ident),
+    ("Delta", "Δ", Kind::Ident),
+    ("Theta", "Θ", Kind::Ident),
+    ("Lambda", "Λ", Kind::Ident),
+    ("Xi", "Ξ", Kind::Ident),
+    ("Pi", "Π", Kind::Ident),
+    ("Sigma", "Σ", Kind::Ident),
+    ("Upsilon", "Υ", Kind::Ident),
+    ("Phi", "Φ", Kind::Ident),
+    ("Psi", "Ψ", Kind::Ident),
+    ("Omega", "Ω", Kind::Ident),
+    ("infty", "∞", Kind::Ident),
+    ("partial", "∂", Kind::Ident),
+    ("nabla", "∇", Kind::Ident),
+    ("emptyset", "∅", Kind::Ident),
+    ("prime", "′", Kind::Op),
+    ("pm", "±", Kind::Op),
+    ("mp", "∓", Kind::Op),
+    ("times", "×", Kind::Op),
+    ("div", "÷", Kind::Op),
+    ("cdot", "⋅", Kind::Op),
+    ("circ", "∘", Kind::Op),
+    ("wedge", "∧", Kind::Op),
+    ("vee", "∨", Kind::Op),
+    ("neg", "¬", Kind::Op),
+    ("cup", "∪", Kind::Op),
+    ("cap", "∩", Kind::Op),
+    ("setminus", "∖", Kind::Op),
+    ("leq", "≤", Kind::Op),
+    ("le", "≤", Kind::Op),
+    ("geq", "≥", Kind::Op),
+    ("ge", "≥", Kind::Op),
+    ("neq", "≠", Kind::Op),
+    ("ne", "≠", Kind::Op),
+    ("approx", "≈", Kind::Op),
+    ("sim", "∼", Kind::Op),
+    ("equiv", "≡", Kind::Op),
+    ("propto", "∝", Kind::Op),
+    ("in", "∈", Kind::Op),
+    ("notin", "∉", Kind::Op),
+    ("subset", "⊂", Kind::Op),
+    ("subseteq", "⊆", Kind::Op),
+    ("mid", "∣", Kind::Op),
+    ("forall", "∀", Kind::Op),
+    ("exists", "∃", Kind::Op),
+    ("to", "→", Kind::Op),
+    ("rightarrow", "→", Kind::Op),
+    ("leftarrow", "←", Kind::Op),
+    ("leftrightarrow", "↔", Kind::Op),
+    ("Rightarrow", "⇒", Kind::Op),
+    ("implies", "⟹", Kind::Op),
+    ("Leftarrow", "⇐", Kind::Op),
+    ("Leftrightarrow", "⇔", Kind::Op),
+    ("iff", "⟺", Kind::Op),
+    ("ldots", "…", Kind::Op),
+    ("dots", "…", Kind::Op),
+    ("cdots", "⋯", Kind::Op),
+    ("langle", "⟨", Kind::Op),
+    ("rangle", "⟩", Kind::Op),
+    ("lfloor", "⌊", Kind::Op),
+    ("rfloor", "⌋", Kind::Op),
+    ("lceil", "⌈", Kind::Op),
+    ("rceil", "⌉", Kind::Op),
+    ("vert", "|", Kind::Op),
+    ("Vert", "‖", Kind::Op),
+    ("sum", "∑", Kind::LargeOp),
+    ("prod", "∏", Kind::LargeOp),
+    ("int", "∫", Kind::Op),
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "det", "dim", "ker", "deg", "gcd", "arg", "Pr",
+];
+
+/// functions that take their scripts as limits in display mode
+const LIMIT_FUNCTIONS: &[&str] = &["lim", "liminf", "limsup", "max", "min", "sup", "inf"];
+
+const ACCENTS: &[(&str, &str)] = &[
+    ("hat", "^"),
+    ("bar", "¯"),
+    ("overline", "¯"),
+    ("vec", "→"),
+    ("dot", "˙"),
+    ("tilde", "~"),
+];
+
+const VARIANTS: &[(&str, &str)] = &[
+    ("mathrm", "normal"),
+    ("mathbf", "bold"),
+    ("mathit", "italic"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+];
+
+const SPACES: &[(&str, &str)] = &[("quad", "1em"), ("qquad", "2em")];
+
+/// commands that only change sizes, which we ignore
+const SIZES: &[&str] = &[
+    "big",
+    "Big",
+    "bigg",
+    "Bigg",
+    "bigl",
+    "bigr",
+    "Bigl",
+    "Bigr",
+    "displaystyle",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// `mathvariant` applied to identifiers, set by commands like `\mathbf`
+    variant: Option<&'static str>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// parses nodes until the end of the current group, row or cell
+    fn parse_row(&mut self) -> Result<String> {
+        let mut out = String::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Close | Token::Align | Token::Newline => break,
+                Token::Command(c) if c == "end" || c == "right" => break,
+                _ => out.push_str(&self.parse_scripted()?),
+            }
+        }
+        Ok(out)
+    }
+
+    /// parses an atom along with its sub and superscripts
+    fn parse_scripted(&mut self) -> Result<String> {
+        let (base, limits) = match self.peek() {
+            Some(Token::Sub | Token::Sup) => ("<mrow></mrow>".to_string(), false),
+            _ => self.parse_atom()?,
+        };
+
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            match self.peek() {
+                Some(Token::Sub) if sub.is_none() => {
+                    self.next();
+                    sub = Some(self.parse_argument()?);
+                }
+                Some(Token::Sup) if sup.is_none() => {
+                    self.next();
+                    sup = Some(self.parse_argument()?);
+                }
+                Some(Token::Char('\'')) if sup.is_none() => {
+                    self.next();
+                    sup = Some("<mo>′</mo>".to_string());
+                }
+                _ => break,
+            }
+        }
+
+        let (under, over, both) = if limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{under}>{base}{sub}</{under}>"),
+            (None, Some(sup)) => format!("<{over}>{base}{sup}</{over}>"),
+            (Some(sub), Some(sup)) => format!("<{both}>{base}{sub}{sup}</{both}>"),
+        })
+    }
+
+    /// parses a required argument, either a `{group}` or a single atom
+    fn parse_argument(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(_) => Ok(self.parse_atom()?.0),
+            None => bail!("missing argument"),
+        }
+    }
+
+    /// reads the raw name inside braces, like the `pmatrix` in `\begin{pmatrix}`
+    fn parse_name(&mut self) -> Result<String> {
+        if self.next() != Some(Token::Open) {
+            bail!("expected {{");
+        }
+        let mut name = String::new();
+        loop {
+            match self.next() {
+                Some(Token::Close) => break,
+                Some(Token::Char(c)) => name.push(c),
+                Some(Token::Number(n)) => name.push_str(&n),
+                Some(Token::Command(c)) => name.push_str(&c),
+                _ => bail!("unclosed {{"),
+            }
+        }
+        Ok(name)
+    }
+
+    /// returns the node, and whether it takes its scripts as limits
+    fn parse_atom(&mut self) -> Result<(String, bool)> {
+        let Some(token) = self.next() else {
+            bail!("unexpected end of expression");
+        };
+
+        let node = match token {
+            Token::Open => {
+                let row = self.parse_row()?;
+                if self.next() != Some(Token::Close) {
+                    bail!("unclosed {{");
+                }
+                format!("<mrow>{row}</mrow>")
+            }
+            Token::Close => bail!("unexpected }}"),
+            Token::Sub | Token::Sup => bail!("double subscript or superscript"),
+            Token::Align | Token::Newline => {
+                bail!("unexpected & or \\\\ outside of an environment")
+            }
+            Token::Number(n) => format!("<mn>{n}</mn>"),
+            Token::Space(width) => format!("<mspace width=\"{width}\"></mspace>"),
+            Token::Char(c) if c.is_alphabetic() => self.identifier(&c.to_string()),
+            Token::Char(c) => {
+                let c = if c == '\'' { '′' } else { c };
+                format!("<mo>{}</mo>", HtmlEscape(c.to_string()))
+            }
+            Token::Text(command, text) => match command.as_str() {
+                "operatorname" => format!("<mi mathvariant=\"normal\">{}</mi>", HtmlEscape(text)),
+                "textbf" => format!("<mtext mathvariant=\"bold\">{}</mtext>", HtmlEscape(text)),
+                "textit" => format!("<mtext mathvariant=\"italic\">{}</mtext>", HtmlEscape(text)),
+                _ => format!("<mtext>{}</mtext>", HtmlEscape(text)),
+            },
+            Token::Command(name) => return self.parse_command(name),
+        };
+
+        Ok((node, false))
+    }
+
+    fn identifier(&self, name: &str) -> String {
+        match self.variant {
+            Some(variant) => format!("<mi mathvariant=\"{variant}\">{}</mi>", HtmlEscape(name)),
+            None => format!("<mi>{}</mi>", HtmlEscape(name)),
+        }
+    }
+
+    fn parse_command(&mut self, name: String) -> Result<(String, bool)> {
+        let name = name.as_str();
+
+        if let Some((_, symbol, kind)) = SYMBOLS.iter().find(|(n, ..)| *n == name) {
+            return Ok(match kind {
+                Kind::Ident => (self.identifier(symbol), false),
+                Kind::Op => (format!("<mo>{symbol}</mo>"), false),
+                Kind::LargeOp => (
+                    format!("<mo largeop=\"true\" movablelimits=\"true\">{symbol}</mo>"),
+                    true,
+                ),
+            });
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok((format!("<mi>{name}</mi>"), false));
+        }
+        if LIMIT_FUNCTIONS.contains(&name) {
+            return Ok((format!("<mo movablelimits=\"true\">{name}</mo>"), true));
+        }
+        if let Some((_, accent)) = ACCENTS.iter().find(|(n, _)| *n == name) {
+            let base = self.parse_argument()?;
+            return Ok((
+                format!("<mover accent=\"true\">{base}<mo>{accent}</mo></mover>"),
+                false,
+            ));
+        }
+        if let Some((_, variant)) = VARIANTS.iter().find(|(n, _)| *n == name) {
+            let previous = self.variant.replace(variant);
+            let node = self.parse_argument();
+            self.variant = previous;
+            return Ok((node?, false));
+        }
+        if let Some((_, width)) = SPACES.iter().find(|(n, _)| *n == name) {
+            return Ok((format!("<mspace width=\"{width}\"></mspace>"), false));
+        }
+        if SIZES.contains(&name) {
+            return match self.peek() {
+                Some(Token::Sub | Token::Sup) | None => Ok((String::new(), false)),
+                _ => self.parse_atom(),
+            };
+        }
+
+        let node = match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let num = self.parse_argument()?;
+                let den = self.parse_argument()?;
+                format!("<mfrac>{num}{den}</mfrac>")
+            }
+            "sqrt" => {
+                if self.peek() == Some(&Token::Char('[')) {
+                    self.next();
+                    let mut index = String::new();
+                    while !matches!(self.peek(), Some(Token::Char(']')) | None) {
+                        index.push_str(&self.parse_scripted()?);
+                    }
+                    if self.next().is_none() {
+                        bail!("unclosed [ in \\sqrt");
+                    }
+                    let base = self.parse_argument()?;
+                    format!("<mroot>{base}<mrow>{index}</mrow></mroot>")
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_argument()?)
+                }
+            }
+            "left" => {
+                let open = self.parse_delimiter()?;
+                let row = self.parse_row()?;
+                if self.next() != Some(Token::Command("right".to_string())) {
+                    bail!("\\left without \\right");
+                }
+                let close = self.parse_delimiter()?;
+                format!("<mrow>{open}{row}{close}</mrow>")
+            }
+            "right" => bail!("\\right without \\left"),
+            "begin" => self.parse_environment()?,
+            "end" => bail!("\\end without \\begin"),
+            _ => bail!("unknown command \\{name}"),
+        };
+
+        Ok((node, false))
+    }
+
+    /// parses the delimiter after `\left` and `\right`, where `.` means no delimiter
+    fn parse_delimiter(&mut self) -> Result<String> {
+        let delimiter = match self.next() {
+            Some(Token::Char('.')) => return Ok(String::new()),
+            Some(Token::Char(c)) => c.to_string(),
+            Some(Token::Command(name)) => SYMBOLS
+                .iter()
+                .find(|(n, ..)| *n == name)
+                .map(|(_, symbol, _)| symbol.to_string())
+                .ok_or_else(|| eyre!("unknown delimiter \\{name}"))?,
+            _ => bail!("missing delimiter"),
+        };
+        Ok(format!(
+            "<mo stretchy=\"true\">{}</mo>",
+            HtmlEscape(delimiter)
+        ))
+    }
+
+    fn parse_environment(&mut self) -> Result<String> {
+        let name = self.parse_name()?;
+        let base = name.trim_end_matches('*');
+
+        let mut rows = vec![vec![]];
+        loop {
+            let cell = self.parse_row()?;
+            rows.last_mut().unwrap().push(cell);
+            match self.next() {
+                Some(Token::Align) => {}
+                Some(Token::Newline) => rows.push(vec![]),
+                Some(Token::Command(c)) if c == "end" => {
+                    let end = self.parse_name()?;
+                    if end != name {
+                        bail!("\\begin{{{name}}} ended by \\end{{{end}}}");
+                    }
+                    break;
+                }
+                _ => bail!("unclosed \\begin{{{name}}}"),
+            }
+        }
+        // a trailing \\ leaves an empty row behind
+        if rows.len() > 1 && rows.last().is_some_and(|r| r.len() == 1 && r[0].is_empty()) {
+            rows.pop();
+        }
+
+        let (open, close, align) = match base {
+            "equation" => {
+                let mut out = String::new();
+                for cell in rows.iter().flatten() {
+                    out.push_str(cell);
+                }
+                return Ok(format!("<mrow>{out}</mrow>"));
+            }
+            "align" | "aligned" => ("", "", "right left"),
+            "matrix" => ("", "", "center"),
+            "pmatrix" => ("(", ")", "center"),
+            "bmatrix" => ("[", "]", "center"),
+            "vmatrix" => ("|", "|", "center"),
+            "cases" => ("{", "", "left left"),
+            _ => bail!("unknown environment {name}"),
+        };
+
+        let mut table = String::new();
+        let _ = write!(table, "<mtable columnalign=\"{align}\">");
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                let _ = write!(table, "<mtd>{cell}</mtd>");
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        let fence = |d: &str| {
+            if d.is_empty() {
+                String::new()
+            } else {
+                format!("<mo stretchy=\"true\">{}</mo>", HtmlEscape(d))
+            }
+        };
+        Ok(format!(
+            "<mrow>{}{table}{}</mrow>",
+            fence(open),
+            fence(close)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_fragments() {
+        assert_eq!(
+            split_fragments(r"if \(x^2\) then \[y\] costs $5 and $10, $a$."),
+            vec![
+                Fragment::Text("if "),
+                Fragment::Math {
+                    latex: "x^2",
+                    display: false
+                },
+                Fragment::Text(" then "),
+                Fragment::Math {
+                    latex: "y",
+                    display: true
+                },
+                Fragment::Text(" costs $5 and $10, "),
+                Fragment::Math {
+                    latex: "a",
+                    display: false
+                },
+                Fragment::Text("."),
+            ]
+        );
+        assert_eq!(
+            split_fragments("\\begin{equation}\nx = 1\n\\end{equation}"),
+            vec![Fragment::Math {
+                latex: "\\begin{equation}\nx = 1\n\\end{equation}",
+                display: true
+            }]
+        );
+        assert_eq!(
+            split_fragments(r"unclosed \( here"),
+            vec![Fragment::Text(r"unclosed \( here")]
+        );
+
+        // whitespace just inside the dollars, or a closing dollar followed by a digit or letter
+        for text in ["$ a$", "$a $", "$5$10", "$a$b", "$a\nb\nc\nd$"] {
+            assert_eq!(split_fragments(text), vec![Fragment::Text(text)]);
+        }
+        assert_eq!(
+            split_fragments("$a$-b"),
+            vec![
+                Fragment::Math {
+                    latex: "a",
+                    display: false
+                },
+                Fragment::Text("-b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_display_math() {
+        assert_eq!(
+            Some(vec!["x = 1", "\\begin{align} y &= 2 \\end{align}"]),
+            display_math("\\[x = 1\\]\n\\begin{align} y &= 2 \\end{align}\n")
+        );
+        assert_eq!(Some(vec!["z"]), display_math("$$z$$"));
+        assert_eq!(None, display_math("\\newpage"));
+        assert_eq!(None, display_math("\\[x\\] and text"));
+        assert_eq!(None, display_math("\\(x\\)"));
+    }
+
+    #[test]
+    fn converts_scripts_and_fractions() {
+        assert_eq!(
+            "<math><mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mfrac><mn>1</mn><mrow><mi>α</mi></mrow></mfrac></mrow></math>",
+            latex_to_mathml(r"x^2 + \frac 1{\alpha}", false).unwrap()
+        );
+        assert_eq!(
+            "<math display=\"block\"><mrow><munderover><mo largeop=\"true\" movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>0</mn></mrow><mi>n</mi></munderover><msqrt><mrow><mi>i</mi></mrow></msqrt></mrow></math>",
+            latex_to_mathml(r"\sum_{i=0}^n \sqrt{i}", true).unwrap()
+        );
+    }
+
+    #[test]
+    fn converts_environments() {
+        assert_eq!(
+            "<math><mrow><mrow><mo stretchy=\"true\">(</mo><mtable columnalign=\"center\"><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable><mo stretchy=\"true\">)</mo></mrow></mrow></math>",
+            latex_to_mathml(r"\begin{pmatrix} 1 & 0 \\ 0 & 1 \end{pmatrix}", false).unwrap()
+        );
+    }
+
+    #[test]
+    fn converts_what_posts_use() {
+        for latex in [
+            r"\alpha + \Omega \leq \infty",
+            r"\sqrt[3]{x} \cdot \frac{\partial f}{\partial x}",
+            r"\left( \frac{a}{b} \right)",
+            r"\text{if } x \in \mathbb{R}, \mathbf{v} \neq \mathcal{O}",
+            r"\hat{x} \vec v \overline{z}",
+            r"\lim_{n \to \infty} \sin x = \max_i a_i",
+            r"x \quad y \, z",
+            r"\Big( x \Big)",
+            r"\begin{equation} e^{i\pi} = -1 \end{equation}",
+            r"\begin{bmatrix} 1 \\ 2 \end{bmatrix} \begin{vmatrix} a & b \end{vmatrix}",
+        ] {
+            assert!(latex_to_mathml(latex, true).is_ok(), "{latex}");
+        }
+
+        assert_eq!(
+            "<math display=\"block\"><mrow><mrow><mtable columnalign=\"right left\"><mtr><mtd><mi>a</mi></mtd><mtd><mo>=</mo><mi>b</mi></mtd></mtr></mtable></mrow></mrow></math>",
+            latex_to_mathml(r"\begin{align} a &= b \\ \end{align}", true).unwrap()
+        );
+        assert_eq!(
+            "<math><mrow><mrow><mo stretchy=\"true\">{</mo><mtable columnalign=\"left left\"><mtr><mtd><mn>1</mn></mtd><mtd><mtext>else</mtext></mtd></mtr></mtable></mrow></mrow></math>",
+            latex_to_mathml(r"\begin{cases} 1 & \text{else} \end{cases}", false).unwrap()
+        );
+
+        // less common latex isn't supported
+        for latex in [
+            r"\binom{n}{k}",
+            r"\mathfrak{g}",
+            r"\begin{gather} x \end{gather}",
+        ] {
+            assert!(latex_to_mathml(latex, false).is_err(), "{latex}");
+        }
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            "unknown command \\foo",
+            latex_to_mathml(r"\foo{x}", false).unwrap_err().to_string()
+        );
+        assert!(latex_to_mathml(r"\frac{1}{2", false).is_err());
+        assert!(latex_to_mathml(r"\begin{pmatrix} 1", false).is_err());
+    }
+}
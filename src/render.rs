@@ -1,23 +1,29 @@
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, HashMap},
+    collections::HashMap,
     fmt::Write as FmtWrite,
-    io::{Error, Write},
+    io::{Error, Read, Write},
     marker::PhantomData,
     rc::Rc,
-    sync::OnceLock,
 };
 
-use color_eyre::{eyre::Context as EyreContext, Report, Result};
+use color_eyre::{
+    eyre::{bail, Context as EyreContext, ContextCompat},
+    Report, Result,
+};
 use orgize::{
-    elements::FnRef,
+    elements::{FnRef, SourceBlock, SpecialBlock, Table, Timestamp},
     export::{DefaultHtmlHandler, HtmlEscape, HtmlHandler, SyntectHtmlHandler},
-    indextree::NodeEdge,
+    indextree::{NodeEdge, NodeId},
     syntect::{
         easy::HighlightLines,
-        highlighting::{Theme, ThemeSet},
-        html::{styled_line_to_highlighted_html, IncludeBackground},
-        parsing::SyntaxSet,
+        highlighting::ThemeSet,
+        html::{
+            css_for_theme_with_class_style, styled_line_to_highlighted_html, ClassStyle,
+            ClassedHTMLGenerator, IncludeBackground,
+        },
+        parsing::{SyntaxReference, SyntaxSet},
+        util::LinesWithEndings,
     },
     Element, Event, Headline, Org,
 };
@@ -26,9 +32,24 @@ use tera::{Context, Tera};
 use vfs::VfsPath;
 
 use crate::{
+    citations::{split_citations, Citations, Part},
+    crossrefs::CrossReferences,
+    dot::render_dot,
+    embeds::Embeds,
+    execute::{can_execute, execute},
+    footnotes::{anonymous_label, reference_id, Footnotes},
+    helpers::parse_plist,
+    images::{display_size, Resizer},
+    lists::{Checkbox, ListKind, Lists, TextInfo},
     macros::Macros,
+    math::{display_math, latex_to_mathml, split_fragments, Fragment},
     page::{Page, PageEnum},
+    source_block::{split_html_lines, Exports, HeaderArgs},
+    table::TableBuilder,
+    tangle::write_tangled_files,
     tera::get_template,
+    timestamps::time_html,
+    typography::smarten,
     Config,
 };
 
@@ -59,11 +80,13 @@ impl<'a> Page<'a> {
             println!("writing {}", out_path.as_str());
         }
 
-        let context = self.page_context(org, macros.clone(), config)?;
+        let (context, tangled_files) = self.page_context(org, macros.clone(), config)?;
 
         render_template(tera, &template, &context, out_path.clone(), hotreloading)
             .with_context(|| format!("rendering {}", &self.info.title))?;
 
+        write_tangled_files(&tangled_files, &out_path)?;
+
         if let PageEnum::Index { children } = &self.page {
             let children = children
                 .values()
@@ -112,11 +135,13 @@ pub fn render_template(
 }
 
 /// renders html for a post
+///
+/// errors are reported along with the headline they happened in
 pub fn write_html(
     headline: &Headline,
     org: &Org<'_>,
     mut handler: impl HtmlHandler<Report>,
-) -> String {
+) -> Result<String> {
     let it = headline
         .headline_node()
         .traverse(org.arena())
@@ -126,45 +151,160 @@ pub fn write_html(
         });
 
     let mut w = Vec::new();
+    let mut current_headline = &headline.title(org).raw;
 
     for event in it {
         match event {
-            Event::Start(element) => handler
-                .start(&mut w, element)
-                .expect("failed to write to html"),
-            Event::End(element) => handler
-                .end(&mut w, element)
-                .expect("failed to write to html"),
+            Event::Start(element) => {
+                if let Element::Title(title) = element {
+                    current_headline = &title.raw;
+                }
+                handler.start(&mut w, element)
+            }
+            Event::End(element) => handler.end(&mut w, element),
         }
+        .wrap_err_with(|| format!("in headline '{current_headline}'"))?;
     }
 
-    String::from_utf8(w).expect("org file should contain valid utf8")
+    Ok(String::from_utf8(w).expect("org file should contain valid utf8"))
 }
 
-static SYNTECT: OnceLock<(SyntaxSet, BTreeMap<String, Theme>)> = OnceLock::new();
+/// the nodes under a headline that get rendered, in order
+///
+/// index pages only render their own section, without the child pages, and posts everything but their title.
+/// footnote definitions are rendered separately, so they aren't included either, and neither are `#+begin_macro`
+/// definitions or `#+RESULTS:` that aren't shown, following the same rules as [`CommonHtmlHandler`]
+pub fn rendered_nodes(
+    org: &Org<'_>,
+    headline: &Headline,
+    index: bool,
+    config: &Config,
+) -> Vec<NodeId> {
+    let root = if index {
+        match headline.section_node() {
+            Some(section) => section,
+            None => return vec![],
+        }
+    } else {
+        headline.headline_node()
+    };
+
+    let mut nodes = Vec::new();
+    let mut skipping = None;
+    let mut src_exports = None;
+    let mut results = None;
+    for edge in root.traverse(org.arena()) {
+        let node = match edge {
+            NodeEdge::Start(node) if skipping.is_none() => node,
+            NodeEdge::End(node) if skipping == Some(node) => {
+                skipping = None;
+                continue;
+            }
+            _ => continue,
+        };
 
-pub fn html_handler(
-    systax_highlighting_theme: String,
-) -> SyntectHtmlHandler<std::io::Error, DefaultHtmlHandler> {
-    let (syntax_set, themes) = SYNTECT.get_or_init(|| {
-        (
-            SyntaxSet::load_defaults_newlines(),
-            ThemeSet::load_defaults().themes,
-        )
-    });
+        let element = &org[node];
+        // `#+RESULTS:` only applies to the element right after it
+        if !matches!(element, Element::Keyword(_)) {
+            if results.take() == Some(false) {
+                skipping = Some(node);
+                continue;
+            }
+            if !matches!(element, Element::SourceBlock(_)) {
+                src_exports = None;
+            }
+        }
+
+        match element {
+            Element::Keyword(keyword) if keyword.key.eq_ignore_ascii_case("results") => {
+                results = Some(src_exports.take().is_none_or(Exports::results));
+            }
+            Element::SourceBlock(block) => {
+                let args = HeaderArgs::parse(&block.arguments);
+                let output = source_block_output(block, &args, config);
+                src_exports = Some(if output.diagram || output.executed {
+                    Exports::Code
+                } else {
+                    args.exports()
+                });
+            }
+            Element::FnDef(_) => {
+                skipping = Some(node);
+                continue;
+            }
+            Element::SpecialBlock(block) if block.name.eq_ignore_ascii_case("macro") => {
+                skipping = Some(node);
+                continue;
+            }
+            _ if node == headline.title_node() => {
+                skipping = Some(node);
+                continue;
+            }
+            _ => {}
+        }
+        nodes.push(node);
+    }
+    nodes
+}
 
+/// renders the children of a node, like the contents of a footnote definition
+pub fn write_children_html(
+    node: NodeId,
+    org: &Org<'_>,
+    mut handler: impl HtmlHandler<Report>,
+) -> Result<String> {
+    let mut w = Vec::new();
+
+    for child in node.children(org.arena()) {
+        for edge in child.traverse(org.arena()) {
+            match edge {
+                NodeEdge::Start(node) => handler.start(&mut w, &org[node])?,
+                NodeEdge::End(node) => handler.end(&mut w, &org[node])?,
+            }
+        }
+    }
+
+    Ok(String::from_utf8(w).expect("org file should contain valid utf8"))
+}
+
+/// classes are prefixed so the generated stylesheets don't clash with the site's own
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "syn-" };
+
+pub fn html_handler(config: &Config) -> SyntectHtmlHandler<std::io::Error, DefaultHtmlHandler> {
     SyntectHtmlHandler {
-        syntax_set: syntax_set.clone(),
+        syntax_set: config.syntaxes.syntax_set.clone(),
         theme_set: ThemeSet {
-            themes: themes.clone(),
+            themes: config.syntaxes.themes.clone(),
         },
-        theme: systax_highlighting_theme,
+        theme: config.syntax_highlighting_theme.clone(),
         inner: DefaultHtmlHandler,
         background: IncludeBackground::No,
         error_type: PhantomData,
     }
 }
 
+/// writes `syntax-light.css` and `syntax-dark.css` into the build folder, for class based syntax highlighting
+pub fn write_syntax_stylesheets(config: &Config) -> Result<()> {
+    for (file, theme) in [
+        ("syntax-light.css", &config.syntax_highlighting_theme),
+        ("syntax-dark.css", &config.syntax_highlighting_dark_theme),
+    ] {
+        let theme = config
+            .syntaxes
+            .themes
+            .get(theme)
+            .with_context(|| format!("Syntax highlighting theme '{theme}' doesn't exist"))?;
+        let css = css_for_theme_with_class_style(theme, CLASS_STYLE);
+        config
+            .build_path
+            .join(file)?
+            .create_file()?
+            .write_all(css.as_bytes())?;
+    }
+
+    Ok(())
+}
+
 #[derive(Default)]
 pub struct IndexHtmlHandler {
     pub handler: CommonHtmlHandler,
@@ -217,6 +357,8 @@ pub struct PostHtmlHandler {
     pub handler: CommonHtmlHandler,
     pub level: usize,
     pub in_page_title: bool,
+    /// `Some` when subheadlines should be prefixed with their section number
+    pub section_numbers: Option<SectionNumbers>,
 }
 
 impl HtmlHandler<Report> for PostHtmlHandler {
@@ -226,6 +368,7 @@ impl HtmlHandler<Report> for PostHtmlHandler {
                 self.in_page_title = true;
             }
             Element::Title(title) => {
+                self.handler.headline = title.raw.to_string();
                 write!(
                     w,
                     "<h{0} {2}><a id=\"{1}\" href=\"#{1}\">",
@@ -233,6 +376,10 @@ impl HtmlHandler<Report> for PostHtmlHandler {
                     slugify(&title.raw),
                     self.handler.render_attributes(""),
                 )?;
+                if let Some(numbers) = &mut self.section_numbers {
+                    let number = numbers.next(title.level - self.level);
+                    write!(w, "<span class=\"section-number\">{number}</span> ")?;
+                }
             }
             _ if !self.in_page_title => {
                 // fallthrough to default handler
@@ -261,18 +408,482 @@ impl HtmlHandler<Report> for PostHtmlHandler {
     }
 }
 
+/// keeps track of hierarchical section numbers ("2.3") while walking a post's headlines
+#[derive(Default, Debug, Clone)]
+pub struct SectionNumbers {
+    counters: Vec<usize>,
+}
+
+impl SectionNumbers {
+    /// returns the number for the next headline `depth` levels below the page title
+    ///
+    /// skipped levels are numbered as 0, like org does
+    pub fn next(&mut self, depth: usize) -> String {
+        self.counters.resize(depth.max(1), 0);
+        if let Some(last) = self.counters.last_mut() {
+            *last += 1;
+        }
+
+        self.counters
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
 #[derive(Default)]
 pub struct CommonHtmlHandler {
     pub handler: SyntectHtmlHandler<std::io::Error, DefaultHtmlHandler>,
     pub config: Config,
 
     pub attributes: HashMap<String, String>,
+    /// set with `#+NAME:` on the next element
+    pub name: Option<String>,
+    /// the footnotes of the page, to number references
+    pub footnotes: Rc<Footnotes>,
+    /// anonymous footnotes seen so far
     pub footnote_id: usize,
+    /// how many times each footnote has been referenced so far
+    pub footnote_refs: HashMap<String, usize>,
+    /// write footnote definitions next to their first reference
+    pub sidenotes: bool,
+    /// write paragraphs as spans, so the html can go inside of a paragraph
+    pub inline_paragraphs: bool,
+    /// the entries cited in the page, to render citations
+    pub citations: Rc<Citations>,
+    /// the named elements of the page, to resolve `[[name]]` links
+    pub cross_references: Rc<CrossReferences>,
+    /// the last character of text written in the current block, to tell opening quotes from closing ones
+    pub previous_char: Option<char>,
+    /// checkboxes, counters and description terms of the lists of the page
+    pub lists: Rc<Lists>,
+    /// the video links that are embedded, and the paragraphs they are in
+    pub embeds: Rc<Embeds>,
+    /// whether we are in the term of a description item
+    pub in_term: bool,
+    /// whether the first paragraph of a description item is open, and wasn't written as a `<p>`
+    pub term_paragraph: bool,
 
     pub macros: Rc<Macros>,
+
+    /// path of the page being rendered, like `/blog/post`
+    pub page_path: String,
+    /// title of the headline being rendered, for warnings
+    pub headline: String,
+
+    /// the org table currently being rendered, if any
+    pub table: Option<TableBuilder>,
+
+    /// what the last source block exports, which decides whether its `#+RESULTS:` are shown
+    pub src_exports: Option<Exports>,
+    /// set by `#+RESULTS:`, whether the element after it should be shown
+    pub results: Option<bool>,
+    /// how deep we are inside an element that's being skipped
+    pub skip_depth: usize,
+
+    /// special blocks with a template that are being rendered, outermost first
+    pub templated_blocks: Vec<TemplatedBlock>,
+}
+
+/// a special block rendered with a template, whose contents are buffered until it ends
+#[derive(Debug)]
+pub struct TemplatedBlock {
+    template: String,
+    parameters: Option<String>,
+    attributes: HashMap<String, String>,
+    html: Vec<u8>,
+    /// how many of the elements inside the block are open
+    depth: usize,
 }
 
 impl CommonHtmlHandler {
+    pub fn new(config: &Config, macros: Rc<Macros>, page_path: &str) -> Self {
+        Self {
+            handler: html_handler(config),
+            config: config.clone(),
+            macros,
+            page_path: page_path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// writes text, converting any latex fragments in it into mathml, and citations into links to the bibliography
+    fn write_text<W: Write>(&mut self, mut w: W, text: &str) -> Result<()> {
+        for fragment in split_fragments(text) {
+            match fragment {
+                Fragment::Text(text) => {
+                    for part in split_citations(text) {
+                        match part {
+                            Part::Text(text) if self.config.smart_typography => {
+                                let language = self.config.language.as_deref();
+                                let text = smarten(text, self.previous_char, language);
+                                write!(w, "{}", HtmlEscape(text))?
+                            }
+                            Part::Text(text) => write!(w, "{}", HtmlEscape(text))?,
+                            Part::Citation(citation) => {
+                                write!(w, "{}", self.citations.citation_html(&citation)?)?
+                            }
+                        }
+                    }
+                }
+                Fragment::Math { latex, display } => self.write_math(&mut w, latex, display)?,
+            }
+        }
+        if let Some(last) = text.chars().last() {
+            self.previous_char = Some(last);
+        }
+        Ok(())
+    }
+
+    /// math that can't be converted is written as code, so a single unsupported command doesn't fail the whole build
+    fn write_math<W: Write>(&mut self, mut w: W, latex: &str, display: bool) -> Result<()> {
+        match latex_to_mathml(latex, display) {
+            Ok(mathml) => write!(w, "{mathml}")?,
+            Err(err) => {
+                eprintln!(
+                    "warning: failed to convert math `{}` in headline '{}' of {}: {err}",
+                    latex.trim(),
+                    self.headline,
+                    self.page_path
+                );
+                write!(w, "<code class=\"math-error\">{}</code>", HtmlEscape(latex))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// writes a highlighted source block, with its filename, line numbers and highlighted lines
+    ///
+    /// `listing` blocks get their name as id
+    ///
+    /// line numbers are only set as `data-line`, so they can be shown with css without being copied along with the code
+    fn write_source_block<W: Write>(
+        &mut self,
+        mut w: W,
+        block: &SourceBlock,
+        args: &HeaderArgs,
+        listing: bool,
+        caption: Option<String>,
+    ) -> Result<()> {
+        let html = highlight(
+            &self.handler,
+            &block.language,
+            &block.contents,
+            self.config.syntax_highlighting_classes,
+        );
+
+        match self.name.as_ref().filter(|_| listing) {
+            Some(name) => write!(
+                w,
+                "<div class=\"org-src-container\" id=\"{}\">",
+                HtmlEscape(name)
+            )?,
+            None => write!(w, "<div class=\"org-src-container\">")?,
+        }
+        if let Some(file) = args.get("file").or(self.name.as_deref()) {
+            write!(w, "<div class=\"src-filename\">{}</div>", HtmlEscape(file))?;
+        }
+        if let Some(caption) = caption.filter(|_| listing) {
+            write!(w, "<label class=\"org-src-name\">{caption}</label>")?;
+        }
+
+        let first_line = args.line_numbers();
+        let highlighted = args.highlighted_lines();
+        if first_line.is_none() && highlighted.is_empty() {
+            write!(
+                w,
+                "<pre class=\"src src-{}\">{html}</pre></div>",
+                block.language
+            )?;
+            return Ok(());
+        }
+
+        write!(
+            w,
+            "<pre class=\"src src-{}{}\">",
+            block.language,
+            if first_line.is_some() { " linenos" } else { "" }
+        )?;
+        for (i, line) in split_html_lines(&html).iter().enumerate() {
+            let class = if highlighted.iter().any(|r| r.contains(&(i + 1))) {
+                "line highlighted"
+            } else {
+                "line"
+            };
+            match first_line {
+                Some(first) => write!(
+                    w,
+                    "<span class=\"{class}\" data-line=\"{}\">{line}\n</span>",
+                    first + i
+                )?,
+                None => write!(w, "<span class=\"{class}\">{line}\n</span>")?,
+            }
+        }
+        write!(w, "</pre></div>")?;
+
+        Ok(())
+    }
+
+    /// special blocks are rendered with `blocks/{name}.html` if the template exists,
+    /// otherwise `details`, `aside` and `note` get their own elements and the rest become a `<div class="name">`
+    fn start_special_block<W: Write>(&mut self, mut w: W, block: &SpecialBlock) -> Result<()> {
+        let name = block.name.to_lowercase();
+
+        // definitions are handled by `Macros`
+        if name == "macro" {
+            self.skip_depth = 1;
+            return Ok(());
+        }
+
+        let template = format!("blocks/{name}.html");
+        if self
+            .config
+            .block_templates
+            .get_template_names()
+            .any(|t| t == template)
+        {
+            self.templated_blocks.push(TemplatedBlock {
+                template,
+                parameters: block.parameters.as_ref().map(ToString::to_string),
+                attributes: std::mem::take(&mut self.attributes),
+                html: Vec::new(),
+                depth: 0,
+            });
+            return Ok(());
+        }
+
+        match name.as_str() {
+            "details" => {
+                write!(w, "<details {}>", self.render_attributes(""))?;
+                if let Some(summary) = &block.parameters {
+                    write!(w, "<summary>{}</summary>", HtmlEscape(summary.trim()))?;
+                }
+            }
+            "aside" => write!(w, "<aside {}>", self.render_attributes(""))?,
+            "note" => write!(
+                w,
+                "<aside {} role=\"note\">",
+                self.render_attributes("note")
+            )?,
+            _ => write!(w, "<div {}>", self.render_attributes(&name))?,
+        }
+        // the attributes are for the block, not its first child
+        self.attributes.clear();
+        self.name = None;
+        Ok(())
+    }
+
+    fn end_special_block<W: Write>(&mut self, mut w: W, block: &SpecialBlock) -> Result<()> {
+        match block.name.to_lowercase().as_str() {
+            "details" => write!(w, "</details>")?,
+            "aside" | "note" => write!(w, "</aside>")?,
+            _ => write!(w, "</div>")?,
+        }
+        Ok(())
+    }
+
+    fn write_templated_block<W: Write>(&mut self, mut w: W, block: TemplatedBlock) -> Result<()> {
+        let mut context = Context::new();
+        context.insert("content", &String::from_utf8_lossy(&block.html));
+        context.insert("parameters", &block.parameters);
+        context.insert("attributes", &block.attributes);
+
+        let html = self
+            .config
+            .block_templates
+            .render(&block.template, &context)
+            .wrap_err_with(|| format!("failed to render template '{}'", block.template))?;
+        write!(w, "{html}")?;
+        Ok(())
+    }
+
+    /// turns the path of a link into a url relative to the site's root
+    ///
+    /// `./` and `../` links are relative to the page's folder in static (`static/<page path>/`).
+    /// files in the page's own folder must exist, other links that aren't in static, like ones to other pages,
+    /// are left as they are with a warning.
+    /// links into the static folder, like `./static/image.png`, are relative to its root
+    fn resolve_link<'b>(&self, link: &'b str) -> Result<Cow<'b, str>> {
+        let link = link.strip_prefix("file:").unwrap_or(link);
+        let static_root = self.config.static_path.as_str();
+
+        let static_folder = static_root.trim_start_matches('/');
+        if !static_folder.is_empty() {
+            let in_static = ["./", "/", ""].iter().find_map(|prefix| {
+                link.strip_prefix(prefix)?
+                    .strip_prefix(static_folder)?
+                    .strip_prefix('/')
+            });
+            if let Some(path) = in_static {
+                return Ok(Cow::Borrowed(path));
+            }
+        }
+
+        if link.starts_with("./") || link.starts_with("../") {
+            let page_folder = self
+                .config
+                .static_path
+                .join(self.page_path.trim_matches('/'))?;
+            let file = page_folder.join(link)?;
+            // `..` could leave the static folder
+            let path = file
+                .as_str()
+                .strip_prefix(static_root)
+                .filter(|p| p.starts_with('/'));
+            if let Some(path) = path {
+                if file.is_file()? {
+                    return Ok(Cow::Owned(path.trim_start_matches('/').to_string()));
+                }
+            }
+
+            // pages don't have extensions, so anything else in the page's folder is a missing file
+            let in_page_folder = file
+                .as_str()
+                .strip_prefix(page_folder.as_str())
+                .is_some_and(|p| p.starts_with('/'));
+            let extension = link
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.rsplit_once('.'));
+            if in_page_folder && extension.is_some_and(|(_, ext)| ext != "org" && ext != "html") {
+                bail!(
+                    "linked file '{link}' doesn't exist in the page's static folder '{}'",
+                    page_folder.as_str()
+                );
+            }
+            eprintln!(
+                "warning: linked file '{link}' in {} isn't in the static folder",
+                self.page_path
+            );
+        }
+
+        Ok(Cow::Borrowed(link))
+    }
+
+    /// dimensions of an image in the static folder, and the resized copies made of it for `srcset`
+    ///
+    /// returns `None` if it isn't there or its format is unknown
+    fn static_image(&self, path: &str) -> Result<Option<StaticImage>> {
+        if path.contains("://") {
+            return Ok(None);
+        }
+
+        let file = self.config.static_path.join(path.trim_start_matches('/'))?;
+        if !file.is_file()? {
+            return Ok(None);
+        }
+
+        let mut data = Vec::new();
+        file.open_file()?.read_to_end(&mut data)?;
+        let Some((width, height)) = display_size(&data) else {
+            return Ok(None);
+        };
+
+        let mut resizer = Resizer::new(&data, path);
+        let mut variants = Vec::new();
+        for &size in self.config.image_widths.iter().filter(|w| **w < width) {
+            let variant = resizer.resize(size, &self.config.build_path, &self.config.cache_path)?;
+            variants.extend(variant.map(|variant| (variant, size)));
+        }
+
+        Ok(Some(StaticImage {
+            width,
+            height,
+            variants,
+        }))
+    }
+
+    /// writes the text at the start of a list item, without the counter and checkbox that orgize leaves in it
+    ///
+    /// the term of a description item is closed here, and its definition opened
+    fn write_item_text<W: Write>(&mut self, mut w: W, text: &str, info: &TextInfo) -> Result<()> {
+        if let Some(checkbox) = info.checkbox {
+            let checked = if checkbox == Checkbox::Checked {
+                " checked"
+            } else {
+                ""
+            };
+            write!(w, "<input type=\"checkbox\" disabled{checked} /> ")?;
+        }
+
+        match info.term {
+            Some((end, definition)) => {
+                self.write_text(&mut w, &text[info.skip.min(end)..end])?;
+                self.in_term = false;
+                write!(w, "</dt><dd>")?;
+                if self.term_paragraph {
+                    write!(w, "<p>")?;
+                }
+                self.previous_char = None;
+                self.write_text(&mut w, &text[definition..])
+            }
+            None => self.write_text(w, &text[info.skip..]),
+        }
+    }
+
+    /// writes active and inactive timestamps and ranges as `<time>` elements, formatted with the formats in the config
+    ///
+    /// diary timestamps can't be turned into a date, so they are written like orgize does
+    fn write_timestamp<W: Write>(&mut self, mut w: W, timestamp: &Timestamp) -> Result<()> {
+        let (class, start, end) = match timestamp {
+            Timestamp::Active { start, .. } => ("active", start, None),
+            Timestamp::Inactive { start, .. } => ("inactive", start, None),
+            Timestamp::ActiveRange { start, end, .. } => ("active", start, Some(end)),
+            Timestamp::InactiveRange { start, end, .. } => ("inactive", start, Some(end)),
+            Timestamp::Diary { .. } => {
+                let element = Element::Timestamp(timestamp.clone());
+                self.handler.start(&mut w, &element)?;
+                return Ok(());
+            }
+        };
+
+        let date_format = &self.config.timestamp_format;
+        let time_format = &self.config.timestamp_time_format;
+        write!(
+            w,
+            "<span class=\"timestamp {class}\">{}",
+            time_html(start, date_format, time_format)?
+        )?;
+        if let Some(end) = end {
+            write!(w, "–{}", time_html(end, date_format, time_format)?)?;
+        }
+        write!(w, "</span>")?;
+
+        Ok(())
+    }
+
+    /// the caption of the current element, numbered like `Figure 2: caption`
+    ///
+    /// numbers come from [`CrossReferences::collect`], elements it didn't number only get the caption
+    fn caption_html(&self, element: &Element) -> Option<String> {
+        let caption = self.attributes.get("alt")?;
+        Some(
+            match self
+                .cross_references
+                .element(element)
+                .and_then(|target| Some((target.kind, target.number?)))
+            {
+                Some((kind, n)) => format!(
+                    "<span class=\"{}-number\">{} {n}:</span> {}",
+                    kind.label().to_lowercase(),
+                    kind.label(),
+                    HtmlEscape(caption)
+                ),
+                None => HtmlEscape(caption).to_string(),
+            },
+        )
+    }
+
+    /// sets the `#+NAME:` of the current element as its id, so it can be linked to
+    fn name_as_id(&mut self) {
+        if let Some(name) = &self.name {
+            self.attributes
+                .entry("id".to_string())
+                .or_insert_with(|| name.clone());
+        }
+    }
+
     fn render_attributes(&mut self, class: &str) -> String {
         if !class.is_empty() {
             self.attributes
@@ -287,7 +898,7 @@ impl CommonHtmlHandler {
         self.attributes
             .iter()
             .fold(String::new(), |mut output, (k, v)| {
-                let _ = write!(output, " {k}=\"{v}\" ");
+                let _ = write!(output, " {}=\"{}\" ", HtmlEscape(k), HtmlEscape(v));
                 output
             })
     }
@@ -295,20 +906,149 @@ impl CommonHtmlHandler {
 
 impl HtmlHandler<Report> for CommonHtmlHandler {
     fn start<W: Write>(&mut self, mut w: W, element: &Element) -> Result<()> {
+        // skipped elements inside a block still count towards its depth, so it ends at the right element
+        if !self.templated_blocks.is_empty() {
+            let mut block = self.templated_blocks.remove(0);
+            block.depth += 1;
+            let res = self.start(&mut block.html, element);
+            self.templated_blocks.insert(0, block);
+            return res;
+        }
+
+        if self.skip_depth > 0 {
+            self.skip_depth += 1;
+            return Ok(());
+        }
+
+        // `#+RESULTS:` only applies to the element right after it
+        if !matches!(element, Element::Keyword(_)) {
+            if let Some(shown) = self.results.take() {
+                if !shown {
+                    self.attributes.clear();
+                    self.name = None;
+                    self.skip_depth = 1;
+                    return Ok(());
+                }
+                self.attributes
+                    .entry("class".to_string())
+                    .and_modify(|s| s.push_str(" results"))
+                    .or_insert_with(|| "results".to_string());
+            }
+            if !matches!(element, Element::SourceBlock(_)) {
+                self.src_exports = None;
+            }
+        }
+
+        // quotes at the start of a block always open, and code before a quote is like text
+        match element {
+            Element::Paragraph { .. }
+            | Element::Title(_)
+            | Element::ListItem(_)
+            | Element::TableCell(_) => self.previous_char = None,
+            Element::Code { value } | Element::Verbatim { value } => {
+                self.previous_char = value.chars().last()
+            }
+            _ => {}
+        }
+
+        // everything inside a table gets buffered into its cells until the table ends
+        if let Some(mut table) = self.table.take() {
+            let res = match element {
+                Element::TableRow(row) => {
+                    table.start_row(row);
+                    Ok(())
+                }
+                Element::TableCell(_) => {
+                    table.start_cell();
+                    Ok(())
+                }
+                _ => {
+                    if let Element::Text { value } = element {
+                        table.push_text(value);
+                    }
+                    self.start(table.cell_writer(), element)
+                }
+            };
+            self.table = Some(table);
+            return res;
+        }
+
         match element {
             Element::FnRef(FnRef { label, .. }) => {
                 let label = if label.is_empty() {
                     self.footnote_id += 1;
-                    Cow::Owned(format!("{}", self.footnote_id))
+                    anonymous_label(self.footnote_id)
                 } else {
-                    label.clone()
+                    label.to_string()
                 };
+                let n = self.footnote_refs.entry(label.clone()).or_default();
+                *n += 1;
+
+                if self.sidenotes && *n == 1 {
+                    if let Some(footnote) = self.footnotes.get(&label) {
+                        // the checkbox lets the note be toggled on small screens, where there's no margin
+                        write!(
+                            w,
+                            r#"<label for="sn-{label}" id="{}" class="margin-toggle sidenote-number">{}</label><input type="checkbox" id="sn-{label}" class="margin-toggle" /><span id="fn-{label}" class="sidenote">{}</span>"#,
+                            HtmlEscape(reference_id(&label, 1)),
+                            footnote.number,
+                            footnote.definition,
+                            label = HtmlEscape(&label),
+                        )?;
+                        return Ok(());
+                    }
+                }
 
+                // references inside of footnote definitions aren't numbered
+                let number = self
+                    .footnotes
+                    .get(&label)
+                    .map(|f| f.number.to_string())
+                    .unwrap_or_else(|| label.clone());
                 write!(
                     w,
-                    r##"<sup id="fnref-{label}"><a href="#fn-{label}" class="footnote-ref">{label}</a></sup>"##
+                    r##"<sup id="{}"><a href="#fn-{}" class="footnote-ref">{}</a></sup>"##,
+                    HtmlEscape(reference_id(&label, *n)),
+                    HtmlEscape(&label),
+                    HtmlEscape(number),
                 )?;
             }
+            // definitions are rendered separately, see `Footnotes`
+            Element::FnDef(_) => {
+                self.skip_depth = 1;
+            }
+            Element::Text { value } => match self.lists.text(element).cloned() {
+                Some(info) => self.write_item_text(w, value, &info)?,
+                None => self.write_text(w, value)?,
+            },
+            Element::Cookie(_) => match self.lists.cookie(element) {
+                Some(progress) => write!(w, "<code class=\"progress\">{progress}</code>")?,
+                None => self.handler.start(w, element)?,
+            },
+            // latex blocks are often display equations, which can be rendered like any other math
+            Element::ExportBlock(block) if block.data.eq_ignore_ascii_case("latex") => {
+                match display_math(&block.contents) {
+                    Some(equations) => {
+                        for latex in equations {
+                            self.write_math(&mut w, latex, true)?;
+                        }
+                    }
+                    None => eprintln!(
+                        "warning: dropped a latex export block in headline '{}' of {}, only math can be converted",
+                        self.headline, self.page_path
+                    ),
+                }
+            }
+            Element::ExportBlock(block) => {
+                if is_html_backend(&block.data) {
+                    write!(w, "{}", block.contents)?;
+                }
+            }
+            Element::Snippet(snippet) => {
+                if is_html_backend(&snippet.name) {
+                    write!(w, "{}", snippet.value)?;
+                }
+            }
             Element::Macros(call) => {
                 if let Some(macro_processor) = self.macros.get(call.name.as_ref()) {
                     let args = call.arguments.as_deref().unwrap_or_default();
@@ -316,25 +1056,57 @@ impl HtmlHandler<Report> for CommonHtmlHandler {
                     write!(w, "{}", out)?;
                 }
             }
+            // the term of a description item isn't a paragraph, it gets opened after the term instead
+            Element::Paragraph { .. } if self.in_term && !self.term_paragraph => {
+                self.term_paragraph = true;
+            }
+            Element::Paragraph { .. } if self.embeds.paragraph(element) => {}
+            Element::Paragraph { .. } if self.inline_paragraphs => {
+                write!(w, "<span {}>", self.render_attributes("paragraph"))?
+            }
             Element::Paragraph { .. } => write!(w, "<p {}>", self.render_attributes(""))?,
             Element::QuoteBlock(_) => write!(w, "<blockquote {}>", self.render_attributes(""))?,
             Element::CenterBlock(_) => write!(w, "<div {}>", self.render_attributes("center"))?,
             Element::VerseBlock(_) => write!(w, "<p {}>", self.render_attributes("verse"))?,
             Element::Bold => write!(w, "<b {}>", self.render_attributes(""))?,
             Element::List(list) => {
-                if list.ordered {
-                    write!(w, "<ol {}", self.render_attributes("v"))?;
+                let kind = self.lists.list(element).unwrap_or(if list.ordered {
+                    ListKind::Ordered
                 } else {
-                    write!(w, "<ul {}>", self.render_attributes(""))?;
-                }
+                    ListKind::Unordered
+                });
+                let tag = match kind {
+                    ListKind::Unordered => "ul",
+                    ListKind::Ordered => "ol",
+                    ListKind::Description => "dl",
+                };
+                write!(w, "<{tag} {}>", self.render_attributes(""))?;
             }
             Element::Italic => write!(w, "<i {}>", self.render_attributes(""))?,
-            Element::ListItem(_) => write!(w, "<li {}>", self.render_attributes(""))?,
+            Element::ListItem(_) => {
+                let item = self.lists.item(element).cloned().unwrap_or_default();
+                if item.description {
+                    self.in_term = true;
+                    write!(w, "<dt {}>", self.render_attributes(""))?;
+                } else {
+                    // not kept as attributes, those would also end up on the paragraph inside
+                    let mut extra = String::new();
+                    if let Some(counter) = item.counter {
+                        let _ = write!(extra, " value=\"{counter}\"");
+                    }
+                    let attributes = self.attributes.clone();
+                    let class = item.checkbox.map(Checkbox::class).unwrap_or_default();
+                    let attrs = self.render_attributes(class);
+                    self.attributes = attributes;
+                    write!(w, "<li {attrs}{extra}>")?;
+                }
+            }
             Element::Section => write!(w, "<section {}>", self.render_attributes(""))?,
             Element::Strike => write!(w, "<s {}>", self.render_attributes(""))?,
             Element::Underline => write!(w, "<u {}>", self.render_attributes(""))?,
             Element::Document { .. } => write!(w, "<main {}>", self.render_attributes(""))?,
             Element::Title(title) => {
+                self.headline = title.raw.to_string();
                 write!(
                     w,
                     "<h{} {}>",
@@ -344,12 +1116,22 @@ impl HtmlHandler<Report> for CommonHtmlHandler {
             }
 
             Element::Link(link) => {
-                let path = link
-                    .path
-                    .trim_start_matches("file:")
-                    .trim_start_matches(format!("./{}", self.config.static_path.as_str()).as_str())
-                    .trim_start_matches(format!(".{}", self.config.static_path.as_str()).as_str())
-                    .trim_start_matches(format!("/{}", self.config.static_path.as_str()).as_str());
+                if let Some(target) = self.cross_references.get(&link.path) {
+                    let text = match &link.desc {
+                        Some(desc) => desc.to_string(),
+                        None => target.label().unwrap_or_else(|| link.path.to_string()),
+                    };
+                    write!(
+                        w,
+                        "<a href=\"#{}\" class=\"crossref\">{}</a>",
+                        HtmlEscape(&link.path),
+                        HtmlEscape(text)
+                    )?;
+                    return Ok(());
+                }
+
+                let path = self.resolve_link(&link.path)?;
+                let path = path.as_ref();
                 let mut attrs = self.render_attributes("");
 
                 let lower = path.to_lowercase();
@@ -357,24 +1139,109 @@ impl HtmlHandler<Report> for CommonHtmlHandler {
                 let base_url = url::Url::parse(&self.config.url)?;
                 let url = base_url.join(path)?;
 
-                if lower.ends_with(".jpg")
-                    || lower.ends_with(".jpeg")
-                    || lower.ends_with(".png")
-                    || lower.ends_with(".gif")
-                    || lower.ends_with(".webp")
-                {
+                if is_image(&lower) {
+                    let mut size = String::new();
+                    if let Some(image) = self.static_image(path)? {
+                        // dimensions avoid layout shifts while the image loads, unless they were set with ATTR_HTML
+                        if !self.attributes.contains_key("width")
+                            && !self.attributes.contains_key("height")
+                        {
+                            write!(
+                                size,
+                                " width=\"{}\" height=\"{}\"",
+                                image.width, image.height
+                            )?;
+                        }
+                        if !image.variants.is_empty() && !self.attributes.contains_key("srcset") {
+                            let mut srcset = Vec::new();
+                            for (variant, width) in &image.variants {
+                                srcset.push(format!("{} {width}w", base_url.join(variant)?));
+                            }
+                            srcset.push(format!("{url} {}w", image.width));
+                            write!(
+                                size,
+                                " srcset=\"{}\" sizes=\"{}\"",
+                                HtmlEscape(srcset.join(", ")),
+                                HtmlEscape(&self.config.image_sizes)
+                            )?;
+                        }
+                    }
+
+                    let id = match &self.name {
+                        Some(name) if !self.attributes.contains_key("id") => {
+                            format!(" id=\"{}\"", HtmlEscape(name))
+                        }
+                        _ => String::new(),
+                    };
+
                     write!(
                         w,
-                        "<figure class=\"image\"><img src=\"{}\" {attrs} loading=\"lazy\" />",
+                        "<figure class=\"image\"{id}><img src=\"{}\" {attrs}{size} loading=\"lazy\" />",
                         HtmlEscape(url.as_str()),
                     )?;
-                    if let Some(caption) = self.attributes.get("alt") {
+                    if let Some(caption) = self.caption_html(element) {
+                        write!(w, "<figcaption>{caption}</figcaption>")?;
+                    }
+                    write!(w, "</figure>")?;
+                } else if let Some(tag) = media_tag(&lower) {
+                    // the caption goes in the figcaption, alt isn't valid on media elements
+                    let caption = self.attributes.remove("alt");
+                    self.attributes.remove("title");
+                    // boolean attributes set with `:autoplay t` in ATTR_HTML
+                    for key in ["autoplay", "loop", "muted", "playsinline"] {
+                        if let Some(value) = self.attributes.get_mut(key) {
+                            if value == "t" || value.is_empty() {
+                                *value = key.to_string();
+                            }
+                        }
+                    }
+                    if let Some(poster) = self.attributes.get_mut("poster") {
+                        *poster = base_url.join(poster)?.to_string();
+                    }
+                    let attrs = self.render_attributes("");
+
+                    write!(
+                        w,
+                        "<figure class=\"{tag}\"><{tag} src=\"{url}\" controls preload=\"metadata\" {attrs}><a href=\"{url}\">{}</a></{tag}>",
+                        HtmlEscape(link.desc.as_ref().unwrap_or(&Cow::Borrowed(path))),
+                        url = HtmlEscape(url.as_str()),
+                    )?;
+                    if let Some(caption) = caption {
+                        write!(w, "<figcaption>{}</figcaption>", HtmlEscape(caption))?;
+                    }
+                    write!(w, "</figure>")?;
+                } else if let Some(embed) = self.embeds.link(element).map(str::to_string) {
+                    // the caption is kept as the iframe's title
+                    let caption = self.attributes.remove("alt");
+                    let attrs = self.render_attributes("");
+
+                    write!(
+                        w,
+                        "<figure class=\"embed\"><iframe src=\"{}\" {attrs} loading=\"lazy\" allowfullscreen></iframe>",
+                        HtmlEscape(embed),
+                    )?;
+                    if let Some(caption) = caption {
                         write!(w, "<figcaption>{}</figcaption>", HtmlEscape(caption))?;
                     }
                     write!(w, "</figure>")?;
                 } else {
-                    if path.starts_with("http://") || path.starts_with("https://") {
-                        attrs.push_str(r#" target="_blank" rel="noopener""#);
+                    // a target set with ATTR_HTML takes precedence
+                    // `#+url:` is only required to be valid in release builds
+                    let site_url = self
+                        .config
+                        .site_url
+                        .as_deref()
+                        .and_then(|site_url| url::Url::parse(site_url).ok());
+                    let site_urls = [Some(base_url.clone()), site_url]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>();
+                    let external = classify_link(&url, &site_urls) == LinkKind::External;
+                    if external && !self.attributes.contains_key("target") {
+                        if let Some(target) = &self.config.external_link_target {
+                            let _ =
+                                write!(attrs, r#" target="{}" rel="noopener""#, HtmlEscape(target));
+                        }
                     }
 
                     write!(
@@ -392,30 +1259,116 @@ impl HtmlHandler<Report> for CommonHtmlHandler {
                     self.attributes
                         .insert("title".to_string(), keyword.value.to_string());
                 }
+                if keyword.key.to_lowercase() == "results" {
+                    // results that don't follow a source block, like the ones from `#+CALL:`, are always shown
+                    self.results = Some(
+                        self.src_exports
+                            .take()
+                            .map(Exports::results)
+                            .unwrap_or(true),
+                    );
+                }
+                if keyword.key.to_lowercase() == "name" {
+                    self.name = Some(keyword.value.to_string());
+                }
                 if keyword.key.to_lowercase() == "attr_html" {
-                    // TODO make this accept multiple things
-                    let v = keyword.value.trim_start_matches(':');
-                    if let Some((k, v)) = v.split_once(' ') {
-                        self.attributes.insert(k.to_string(), v.to_string());
+                    // repeated ATTR_HTML lines are merged, with classes accumulating
+                    for (k, v) in parse_plist(&keyword.value) {
+                        match self.attributes.get_mut(&k) {
+                            Some(class) if k == "class" => {
+                                class.push(' ');
+                                class.push_str(&v);
+                            }
+                            _ => {
+                                self.attributes.insert(k, v);
+                            }
+                        }
                     }
                 }
             }
+            Element::Table(Table::Org { .. }) => {
+                // the caption goes in a <caption>, it's not a valid attribute on tables
+                let caption = self.caption_html(element);
+                self.attributes.remove("alt");
+                self.attributes.remove("title");
+                self.name_as_id();
+                self.table = Some(TableBuilder::new(caption, self.render_attributes("")));
+            }
+            Element::FixedWidth(fixed) => write!(
+                w,
+                "<pre {}>{}</pre>",
+                self.render_attributes("example"),
+                HtmlEscape(strip_fixed_width(&fixed.value))
+            )?,
+            Element::ExampleBlock(block) => write!(
+                w,
+                "<pre {}>{}</pre>",
+                self.render_attributes("example"),
+                HtmlEscape(&block.contents)
+            )?,
+            Element::SpecialBlock(block) => self.start_special_block(w, block)?,
+            Element::Timestamp(timestamp) => self.write_timestamp(w, timestamp)?,
             Element::InlineSrc(inline_src) => write!(
                 w,
                 "<code>{}</code>",
-                highlight(&self.handler, &inline_src.lang, &inline_src.body)
+                highlight(
+                    &self.handler,
+                    &inline_src.lang,
+                    &inline_src.body,
+                    self.config.syntax_highlighting_classes
+                )
             )?,
             Element::SourceBlock(block) => {
-                if block.language.is_empty() {
+                let args = HeaderArgs::parse(&block.arguments);
+                let SourceBlockOutput {
+                    code,
+                    diagram,
+                    executed,
+                } = source_block_output(block, &args, &self.config);
+
+                let caption = self.caption_html(element);
+                if code && block.language.is_empty() {
                     write!(w, "<pre class=\"example\">{}</pre>", block.contents)?;
+                } else if code {
+                    // the diagram gets the caption and id instead
+                    self.write_source_block(&mut w, block, &args, !diagram, caption.clone())?;
+                }
+
+                // results rendered here replace the ones saved in the file
+                self.src_exports = Some(if diagram || executed {
+                    Exports::Code
                 } else {
+                    args.exports()
+                });
+
+                if executed {
+                    let output = execute(
+                        &block.language,
+                        &block.contents,
+                        &self.config.root_folder,
+                        &self.config.cache_path,
+                    )?;
                     write!(
                         w,
-                        "<div class=\"org-src-container\"><pre class=\"src src-{}\">{}</pre></div>",
-                        block.language,
-                        highlight(&self.handler, &block.language, &block.contents)
+                        "<pre class=\"example results\">{}</pre>",
+                        HtmlEscape(output)
                     )?;
                 }
+
+                if diagram {
+                    self.attributes.remove("alt");
+                    self.name_as_id();
+                    write!(
+                        w,
+                        "<figure class=\"diagram\" {}>{}",
+                        self.render_attributes(""),
+                        render_dot(&block.contents)?
+                    )?;
+                    if let Some(caption) = caption {
+                        write!(w, "<figcaption>{caption}</figcaption>")?;
+                    }
+                    write!(w, "</figure>")?;
+                }
             }
             _ => {
                 self.handler.start(w, element)?;
@@ -424,11 +1377,84 @@ impl HtmlHandler<Report> for CommonHtmlHandler {
         Ok(())
     }
 
-    fn end<W: Write>(&mut self, w: W, element: &Element) -> Result<()> {
+    fn end<W: Write>(&mut self, mut w: W, element: &Element) -> Result<()> {
+        if !self.templated_blocks.is_empty() {
+            let mut block = self.templated_blocks.remove(0);
+            // nothing inside the block is open, so this is the end of the block itself
+            if block.depth == 0 {
+                self.attributes.clear();
+                self.name = None;
+                return self.write_templated_block(w, block);
+            }
+            block.depth -= 1;
+            let res = self.end(&mut block.html, element);
+            self.templated_blocks.insert(0, block);
+            return res;
+        }
+
+        if self.skip_depth > 0 {
+            self.skip_depth -= 1;
+            return Ok(());
+        }
+
+        if let Some(mut table) = self.table.take() {
+            match element {
+                Element::Table(_) => {
+                    self.attributes.clear();
+                    self.name = None;
+                    write!(w, "{}", table.finish())?;
+                    return Ok(());
+                }
+                Element::TableRow(_) | Element::TableCell(_) => {}
+                _ => self.end(table.cell_writer(), element)?,
+            }
+            self.table = Some(table);
+            return Ok(());
+        }
+
         match element {
             Element::Keyword(_k) => {}
+            Element::Paragraph { .. } if self.term_paragraph => {
+                self.attributes.clear();
+                self.name = None;
+                self.term_paragraph = false;
+                // the paragraph was only opened if the definition started in it
+                if !self.in_term {
+                    write!(w, "</p>")?;
+                }
+            }
+            Element::ListItem(_) if self.lists.item(element).is_some_and(|i| i.description) => {
+                self.attributes.clear();
+                self.name = None;
+                if self.in_term {
+                    self.in_term = false;
+                    write!(w, "</dt>")?;
+                } else {
+                    write!(w, "</dd>")?;
+                }
+            }
+            Element::List(_) if self.lists.list(element) == Some(ListKind::Description) => {
+                self.attributes.clear();
+                self.name = None;
+                write!(w, "</dl>")?;
+            }
+            Element::Paragraph { .. } if self.embeds.paragraph(element) => {
+                self.attributes.clear();
+                self.name = None;
+            }
+            Element::Paragraph { .. } if self.inline_paragraphs => {
+                self.attributes.clear();
+                self.name = None;
+                write!(w, "</span>")?;
+            }
+            Element::SpecialBlock(block) => {
+                self.attributes.clear();
+                self.name = None;
+                self.end_special_block(w, block)?;
+            }
             _ => {
                 self.attributes.clear();
+                self.name = None;
                 self.handler.end(w, element)?;
             }
         }
@@ -437,41 +1463,878 @@ impl HtmlHandler<Report> for CommonHtmlHandler {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkKind {
+    /// a page or file of this site
+    Internal,
+    /// a website somewhere else
+    External,
+    /// schemes that aren't for websites, like `mailto:` or `tel:`
+    Other,
+}
+
+/// classifies a link by its scheme, links to the hosts of the site's urls are internal
+///
+/// that's both the url it's built for and the one it's published at, so links are the same in development and release
+fn classify_link(url: &url::Url, site_urls: &[url::Url]) -> LinkKind {
+    match url.scheme() {
+        "http" | "https" if site_urls.iter().any(|site| url.host() == site.host()) => {
+            LinkKind::Internal
+        }
+        "http" | "https" => LinkKind::External,
+        _ => LinkKind::Other,
+    }
+}
+
+/// whether a lowercase link path is an image
+pub fn is_image(path: &str) -> bool {
+    [".jpg", ".jpeg", ".png", ".gif", ".webp"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+/// an image in the static folder
+struct StaticImage {
+    width: u32,
+    height: u32,
+    /// resized copies, relative to the build folder, with their widths
+    variants: Vec<(String, u32)>,
+}
+
+/// what a source block is rendered as
+pub struct SourceBlockOutput {
+    /// the highlighted code
+    pub code: bool,
+    /// a `dot` block rendered into an svg
+    pub diagram: bool,
+    /// the output of running the block, with `--allow-exec`
+    pub executed: bool,
+}
+
+pub fn source_block_output(
+    block: &SourceBlock,
+    args: &HeaderArgs,
+    config: &Config,
+) -> SourceBlockOutput {
+    let diagram = block.language == "dot" && args.exports().results();
+    let executed = config.allow_exec && args.exports().results() && can_execute(&block.language);
+    SourceBlockOutput {
+        code: args.exports().code(),
+        diagram,
+        executed,
+    }
+}
+
+/// the element used to play a linked media file, if it is one
+fn media_tag(path: &str) -> Option<&'static str> {
+    let extension = path.rsplit_once('.')?.1;
+    match extension {
+        "mp4" | "webm" | "mov" => Some("video"),
+        "mp3" | "ogg" | "flac" | "wav" => Some("audio"),
+        _ => None,
+    }
+}
+
+/// export backends org knows about, content for them is dropped
+const OTHER_BACKENDS: &[&str] = &[
+    "latex",
+    "beamer",
+    "gemini",
+    "md",
+    "markdown",
+    "odt",
+    "ascii",
+    "texinfo",
+    "man",
+    "org",
+    "icalendar",
+];
+
+/// whether raw content for this export backend should be written as is
+///
+/// warns about backends that don't exist, since it's probably a typo
+fn is_html_backend(backend: &str) -> bool {
+    let backend = backend.to_lowercase();
+    if backend == "html" {
+        return true;
+    }
+    if !OTHER_BACKENDS.contains(&backend.as_str()) {
+        eprintln!("warning: unknown export backend '{backend}', its content will be dropped");
+    }
+    false
+}
+
+/// removes the `: ` markers from the lines of a fixed width area, which orgize keeps
+fn strip_fixed_width(value: &str) -> String {
+    value
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            line.strip_prefix(": ")
+                .or_else(|| line.strip_prefix(':'))
+                .unwrap_or(line)
+        })
+        .fold(String::new(), |mut out, line| {
+            out.push_str(line);
+            out.push('\n');
+            out
+        })
+}
+
+/// picks the syntax for a language
+///
+/// php snippets without an opening `<?php` tag use the embedded php syntax, which starts out in php code
+fn find_syntax<'a>(
+    syntax_set: &'a SyntaxSet,
+    language: &str,
+    content: &str,
+) -> &'a SyntaxReference {
+    if language.eq_ignore_ascii_case("php") && !content.starts_with("<?php") {
+        if let Some(syntax) = syntax_set.find_syntax_by_name("PHP Source") {
+            return syntax;
+        }
+    }
+
+    syntax_set
+        .find_syntax_by_token(language)
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text())
+}
+
 // from https://docs.rs/orgize/latest/src/orgize/export/html.rs.html#330
 fn highlight<E: From<Error>, H: HtmlHandler<E>>(
     syntect: &SyntectHtmlHandler<E, H>,
     language: &str,
     content: &str,
+    classed: bool,
 ) -> String {
-    let mut highlighter = HighlightLines::new(
-        syntect
-            .syntax_set
-            .find_syntax_by_token(language)
-            .unwrap_or_else(|| syntect.syntax_set.find_syntax_plain_text()),
-        &syntect.theme_set.themes[&syntect.theme],
-    );
+    let syntax = find_syntax(&syntect.syntax_set, language, content);
 
-    if language.eq_ignore_ascii_case("php") && !content.starts_with("<?php") {
-        let s = format!("<?php {}", content);
-        let regions = highlighter.highlight(&s, &syntect.syntax_set);
-        let mut res = styled_line_to_highlighted_html(&regions[..], syntect.background);
-
-        // TODO this is hard coded to that one theme
-        if res.starts_with("<span style=\"color:#323232;\">&lt;?php ") {
-            res = res
-                .trim_start_matches("<span style=\"color:#323232;\">&lt;?php ")
-                .to_string();
-
-            if res.starts_with("</span>") {
-                res = res.trim_start_matches("</span>").to_string();
-            } else {
-                res = format!("<span style=\"color:#323232;\">{res}");
-            }
+    if classed {
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &syntect.syntax_set, CLASS_STYLE);
+        for line in LinesWithEndings::from(content) {
+            generator.parse_html_for_line_which_includes_newline(line);
         }
-
-        res
+        generator.finalize()
     } else {
+        let mut highlighter =
+            HighlightLines::new(syntax, &syntect.theme_set.themes[&syntect.theme]);
         let regions = highlighter.highlight(content, &syntect.syntax_set);
         styled_line_to_highlighted_html(&regions[..], syntect.background)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{bibliography::Bibliography, citations::CitationStyle, context::common_handler};
+
+    use super::*;
+
+    fn render(source: &str) -> Result<String> {
+        render_with(source, &Config::default())
+    }
+
+    /// renders the first headline as a post, with everything collected ahead of time like a page does
+    fn render_with(source: &str, config: &Config) -> Result<String> {
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let handler = common_handler(
+            &headline,
+            &org,
+            "/blog/post",
+            false,
+            false,
+            Default::default(),
+            config,
+        )?;
+        write_html(&headline, &org, handler)
+    }
+
+    #[test]
+    fn results_follow_exports() -> Result<()> {
+        let html = render(
+            r#"
+* post
+#+begin_src sh :exports both
+echo hi
+#+end_src
+
+#+RESULTS:
+: hi
+
+#+begin_src sh
+echo hidden
+#+end_src
+
+#+RESULTS:
+: hidden
+
+#+RESULTS:
+| a | b |
+"#,
+        )?;
+
+        assert!(html.contains(
+            r#"<pre  class="results example" >hi
+</pre>"#
+        ));
+        assert!(!html.contains(">hidden</pre>"));
+        assert!(html.contains(r#"<table  class="results" >"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_code() -> Result<()> {
+        let html = render(
+            r#"
+* post
+#+begin_src rust :exports none
+fn hidden() {}
+#+end_src
+
+#+RESULTS:
+: hidden results
+
+#+begin_src rust :exports results
+fn replaced() {}
+#+end_src
+
+#+RESULTS:
+: shown results
+"#,
+        )?;
+
+        assert!(!html.contains("hidden"));
+        assert!(!html.contains("replaced"));
+        assert!(!html.contains("src-rust"));
+        assert!(html.contains(">shown results\n</pre>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn executes_only_with_allow_exec() -> Result<()> {
+        let source = "* post\n#+begin_src sh :exports both\necho $((6 * 7))\n#+end_src\n";
+
+        let html = render(source)?;
+        assert!(html.contains(r#"<pre class="src src-sh">"#));
+        assert!(!html.contains("results"));
+
+        let config = Config {
+            allow_exec: true,
+            ..Default::default()
+        };
+        let html = render_with(source, &config)?;
+        assert!(html.contains(
+            r#"<pre class="example results">42
+</pre>"#
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn raw_exports() -> Result<()> {
+        let html = render(
+            r#"
+* post
+#+begin_export html
+<marquee>hi</marquee>
+#+end_export
+
+#+begin_export gemini
+=> gemini://example.com
+#+end_export
+
+#+begin_export latex
+\newpage
+#+end_export
+
+#+begin_export latex
+\[ e^{i\pi} = -1 \]
+#+end_export
+
+some @@html:<kbd>@@C-x@@html:</kbd>@@ and @@latex:\newpage@@
+"#,
+        )?;
+
+        assert!(html.contains("<marquee>hi</marquee>"));
+        assert!(!html.contains("gemini://"));
+        assert!(html.contains("<kbd>C-x</kbd>"));
+        assert!(!html.contains("newpage"));
+        // latex math is converted like the rest of the math
+        assert!(html.contains(r#"<math display="block"><mrow><msup><mi>e</mi>"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn classifies_links() -> Result<()> {
+        let base = url::Url::parse("https://example.com/blog/")?;
+        let site_urls = [base.clone()];
+        let kind =
+            |link: &str| -> Result<LinkKind> { Ok(classify_link(&base.join(link)?, &site_urls)) };
+
+        assert_eq!(LinkKind::Internal, kind("/about")?);
+        assert_eq!(LinkKind::Internal, kind("http://example.com/about")?);
+        assert_eq!(LinkKind::Internal, kind("//example.com/about")?);
+        assert_eq!(LinkKind::External, kind("https://other.org")?);
+        assert_eq!(LinkKind::External, kind("//cdn.other.org/file.js")?);
+        assert_eq!(LinkKind::Other, kind("mailto:me@example.com")?);
+        assert_eq!(LinkKind::Other, kind("tel:+123456")?);
+        assert_eq!(LinkKind::Other, kind("ftp://example.com/file")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn external_link_target() -> Result<()> {
+        let source = "* post\n[[https://other.org][other]] [[mailto:me@example.com][mail]]\n";
+        let mut config = Config {
+            url: "https://example.com".to_string(),
+            ..Default::default()
+        };
+
+        let html = render_with(source, &config)?;
+        assert!(html
+            .contains(r#"<a href="https://other.org/"  target="_blank" rel="noopener">other</a>"#));
+        assert!(html.contains(r#"<a href="mailto:me@example.com" >mail</a>"#));
+
+        // links to the published site are internal when building for development too
+        let dev = Config {
+            url: "http://localhost:1024".to_string(),
+            site_url: Some("https://example.com".to_string()),
+            ..Default::default()
+        };
+        let html = render_with("* post\n[[https://example.com/about][about]]\n", &dev)?;
+        assert!(html.contains(r#"<a href="https://example.com/about" >about</a>"#));
+
+        config.external_link_target = None;
+        let html = render_with(source, &config)?;
+        assert!(html.contains(r#"<a href="https://other.org/" >other</a>"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn embeds() -> Result<()> {
+        let source = r#"
+* post
+watch [[https://www.youtube.com/watch?v=dQw4w9WgXcQ]] now
+
+[[https://youtu.be/dQw4w9WgXcQ]]
+"#;
+        let mut config = Config {
+            url: "http://localhost".to_string(),
+            embed_youtube: true,
+            ..Default::default()
+        };
+        let html = render_with(source, &config)?;
+
+        // links in the middle of text stay links, and embeds aren't put inside of a paragraph
+        assert!(html.contains(r#"<p >watch <a href="https://www.youtube.com/watch?v=dQw4w9WgXcQ""#));
+        assert!(html.contains(r#"</p><figure class="embed"><iframe src="https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ""#));
+        assert_eq!(1, html.matches("<iframe").count());
+
+        // youtube isn't embedded unless it's turned on
+        config.embed_youtube = false;
+        assert!(!render_with(source, &config)?.contains("<iframe"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn media_links() -> Result<()> {
+        let config = Config {
+            url: "http://localhost".to_string(),
+            ..Default::default()
+        };
+        let html = render_with(
+            r#"
+* post
+#+CAPTION: a cat
+#+ATTR_HTML: :loop t
+[[file:cat.webm]]
+"#,
+            &config,
+        )?;
+
+        assert!(html.contains(
+            r#"<figure class="video"><video src="http://localhost/cat.webm" controls preload="metadata"  loop="loop" >"#
+        ));
+        assert!(html.contains("<figcaption>a cat</figcaption></figure>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn image_dimensions() -> Result<()> {
+        let config = Config {
+            url: "http://localhost".to_string(),
+            ..Default::default()
+        };
+        let mut gif = Vec::new();
+        image::RgbaImage::new(640, 480)
+            .write_to(&mut std::io::Cursor::new(&mut gif), image::ImageFormat::Gif)?;
+        config
+            .static_path
+            .join("cat.gif")?
+            .create_file()?
+            .write_all(&gif)?;
+
+        let html = render_with("* post\n[[file:cat.gif]]\n", &config)?;
+        assert!(html.contains(r#"width="640" height="480""#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn responsive_images() -> Result<()> {
+        let config = Config {
+            url: "http://localhost".to_string(),
+            image_widths: vec![40, 200],
+            ..Default::default()
+        };
+        let mut png = Vec::new();
+        image::RgbaImage::new(100, 50)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+        config.static_path.join("photos")?.create_dir_all()?;
+        config
+            .static_path
+            .join("photos/cat.png")?
+            .create_file()?
+            .write_all(&png)?;
+
+        let html = render_with("* post\n[[file:photos/cat.png]]\n", &config)?;
+        // copies are only made for widths smaller than the image
+        assert!(html.contains(
+            r#"width="100" height="50" srcset="http://localhost/photos/cat-40w.webp 40w, http://localhost/photos/cat.png 100w" sizes="100vw""#
+        ));
+
+        let variant = config.build_path.join("photos/cat-40w.webp")?;
+        let mut data = Vec::new();
+        variant.open_file()?.read_to_end(&mut data)?;
+        assert_eq!(Some((40, 20)), display_size(&data));
+
+        // photos stay jpeg
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(300, 100).write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            image::ImageFormat::Jpeg,
+        )?;
+        config
+            .static_path
+            .join("photos/dog.jpg")?
+            .create_file()?
+            .write_all(&jpeg)?;
+        let html = render_with("* post\n[[file:photos/dog.jpg]]\n", &config)?;
+        assert!(html.contains(r#"srcset="http://localhost/photos/dog-40w.jpg 40w, http://localhost/photos/dog-200w.jpg 200w, http://localhost/photos/dog.jpg 300w""#));
+
+        // the cached copy is used while the image doesn't change
+        let cached = config
+            .cache_path
+            .join("images")?
+            .read_dir()?
+            .find(|file| file.as_str().ends_with(".webp"))
+            .unwrap();
+        cached.create_file()?.write_all(b"cached")?;
+        variant.remove_file()?;
+        render_with("* post\n[[file:photos/cat.png]]\n", &config)?;
+        assert_eq!("cached", variant.read_to_string()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn colocated_assets() -> Result<()> {
+        let config = Config {
+            url: "http://localhost".to_string(),
+            ..Default::default()
+        };
+        let folder = config.static_path.join("blog/post")?;
+        folder.create_dir_all()?;
+        folder.join("diagram.png")?.create_file()?;
+        config.static_path.join("blog/shared.pdf")?.create_file()?;
+
+        let html = render_with(
+            "* post\n[[file:./diagram.png]] [[../shared.pdf][shared]] [[file:/images/cat.png]]\n",
+            &config,
+        )?;
+        assert!(html.contains(r#"<img src="http://localhost/blog/post/diagram.png""#));
+        assert!(html.contains(r#"<a href="http://localhost/blog/shared.pdf" >shared</a>"#));
+        assert!(html.contains(r#"<img src="http://localhost/images/cat.png""#));
+
+        assert!(render_with("* post\n[[file:./missing.png]]\n", &config).is_err());
+
+        // other pages and files outside of static are left as they are
+        let html = render_with(
+            "* post\n[[../other-post][other]] [[file:../../../notes.txt][notes]]\n",
+            &config,
+        )?;
+        assert!(html.contains(r#"<a href="http://localhost/other-post" >other</a>"#));
+        assert!(html.contains(r#"<a href="http://localhost/notes.txt" >notes</a>"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn footnote_references() -> Result<()> {
+        let org =
+            Org::parse("* post\nsee[fn:note] and again[fn:note]\n\n[fn:note] the definition\n");
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config::default();
+        let handler = CommonHtmlHandler {
+            footnotes: Rc::new(Footnotes::collect(
+                &org,
+                &headline,
+                &rendered_nodes(&org, &headline, false, &config),
+                || CommonHtmlHandler::new(&config, Default::default(), "/post"),
+            )?),
+            ..CommonHtmlHandler::new(&config, Default::default(), "/post")
+        };
+        let html = write_html(&headline, &org, handler)?;
+
+        assert!(html.contains(
+            r##"see<sup id="fnref-note"><a href="#fn-note" class="footnote-ref">1</a></sup>"##
+        ));
+        assert!(html.contains(
+            r##"again<sup id="fnref-note-2"><a href="#fn-note" class="footnote-ref">1</a></sup>"##
+        ));
+        assert!(!html.contains("the definition"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sidenotes() -> Result<()> {
+        let org =
+            Org::parse("* post\nsee[fn:note] and again[fn:note]\n\n[fn:note] the *definition*\n");
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config::default();
+        let handler = CommonHtmlHandler {
+            footnotes: Rc::new(Footnotes::collect(
+                &org,
+                &headline,
+                &rendered_nodes(&org, &headline, false, &config),
+                || CommonHtmlHandler {
+                    inline_paragraphs: true,
+                    ..CommonHtmlHandler::new(&config, Default::default(), "/post")
+                },
+            )?),
+            sidenotes: true,
+            ..CommonHtmlHandler::new(&config, Default::default(), "/post")
+        };
+        let html = write_html(&headline, &org, handler)?;
+
+        assert!(html.contains(
+            r#"see<label for="sn-note" id="fnref-note" class="margin-toggle sidenote-number">1</label><input type="checkbox" id="sn-note" class="margin-toggle" /><span id="fn-note" class="sidenote"><span  class="paragraph" > the <b >definition</b></span></span>"#
+        ));
+        // later references just point to the note
+        assert!(html.contains(r##"again<sup id="fnref-note-2"><a href="#fn-note""##));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cross_references() -> Result<()> {
+        let source = r#"
+* post
+as [[cat]] and [[numbers][the table]] show, and [[hello]] prints
+
+#+NAME: cat
+#+CAPTION: a cat
+[[https://example.com/cat.png]]
+
+#+begin_src sh
+echo hidden
+#+end_src
+
+#+CAPTION: hidden results
+#+RESULTS:
+| hidden |
+
+#+NAME: numbers
+#+CAPTION: some numbers
+| 1 | 2 |
+
+#+NAME: hello
+#+CAPTION: hello world
+#+begin_src rust
+fn main() {}
+#+end_src
+"#;
+        let org = Org::parse(source);
+        let headline = org.document().first_child(&org).unwrap();
+        let config = Config {
+            url: "http://localhost".to_string(),
+            ..Default::default()
+        };
+        let handler = CommonHtmlHandler {
+            cross_references: Rc::new(CrossReferences::collect(
+                &org,
+                &rendered_nodes(&org, &headline, false, &config),
+                &config,
+            )),
+            ..CommonHtmlHandler::new(&config, Default::default(), "/post")
+        };
+        let html = write_html(&headline, &org, handler)?;
+
+        assert!(html.contains(r##"as <a href="#cat" class="crossref">Figure 1</a>"##));
+        assert!(html.contains(r##"<a href="#numbers" class="crossref">the table</a>"##));
+        assert!(html.contains(r##"<a href="#hello" class="crossref">Listing 1</a>"##));
+
+        assert!(html.contains(r#"<figure class="image" id="cat">"#));
+        assert!(html.contains(
+            r#"<figcaption><span class="figure-number">Figure 1:</span> a cat</figcaption>"#
+        ));
+        assert!(html.contains(r#" id="numbers" "#));
+        assert!(html.contains(
+            r#"<caption><span class="table-number">Table 1:</span> some numbers</caption>"#
+        ));
+        assert!(html.contains(r#"<div class="org-src-container" id="hello">"#));
+        assert!(html.contains(
+            r#"<label class="org-src-name"><span class="listing-number">Listing 1:</span> hello world</label>"#
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn smart_typography() -> Result<()> {
+        let config = Config {
+            smart_typography: true,
+            ..Default::default()
+        };
+        let html = render_with(
+            r#"
+* post
+"quoted" -- and *"bold"* but ~"code"~ isn't touched...
+
+#+begin_src sh
+echo "hi" -- there
+#+end_src
+"#,
+            &config,
+        )?;
+
+        assert!(html.contains(
+            "“quoted” – and <b >“bold”</b> but <code>&quot;code&quot;</code> isn’t touched…"
+        ));
+        assert!(html.contains("&quot;hi&quot;"));
+        assert!(!html.contains("–</span> there"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps() -> Result<()> {
+        let config = Config {
+            timestamp_format: "%B %-d, %Y".to_string(),
+            ..Default::default()
+        };
+        let html = render_with(
+            "* post\nreleased <2023-03-07 Tue>, then [2023-03-08 Wed 14:30]--[2023-03-09 Thu 9:00]\n",
+            &config,
+        )?;
+
+        assert!(html.contains(
+            r#"released <span class="timestamp active"><time datetime="2023-03-07">March 7, 2023</time></span>"#
+        ));
+        assert!(html.contains(
+            r#"<span class="timestamp inactive"><time datetime="2023-03-08T14:30">2023-03-08 14:30</time>–<time datetime="2023-03-09T09:00">2023-03-09 09:00</time></span>"#
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists() -> Result<()> {
+        let source = r#"
+* post
+- [-] tasks [1/2]
+  - [X] done
+  - [ ] todo
+
+text
+
+1. first
+2. [@5] fifth
+3. sixth
+
+text
+
+- ~term~ :: the definition
+- other ::
+  defined later
+
+#+ATTR_HTML: :class fancy
+- [X] styled
+"#;
+        let html = render(source)?;
+
+        assert!(html.contains(
+            r#"<li  class="trans" ><p ><input type="checkbox" disabled /> tasks <code class="progress">[1/2]</code>"#
+        ));
+        assert!(html.contains(
+            r#"<li  class="on" ><p ><input type="checkbox" disabled checked /> done</p></li>"#
+        ));
+
+        assert!(html.contains(r#"<ol ><li ><p >first</p></li><li  value="5"><p >fifth</p></li>"#));
+
+        assert!(html.contains(
+            r#"<dl ><dt ><code>term</code></dt><dd><p>the definition</p></dd><dt >other</dt><dd><p>defined later</p></dd></dl>"#
+        ));
+
+        // the checkbox class joins the one from ATTR_HTML instead of repeating the attribute
+        assert!(html.contains(r#"<li  class="fancy on" >"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn citations() -> Result<()> {
+        let bibliography = Bibliography::parse(
+            r#"
+@article{knuth1984, author = {Knuth, Donald E.}, title = {Literate Programming}, year = 1984}
+@book{sicp, author = {Abelson, Harold and Sussman, Gerald Jay}, title = {SICP}, year = 1996}
+"#,
+        )?;
+        let render_cites = |source: &str, citation_style| -> Result<String> {
+            let org = Org::parse(source);
+            let headline = org.document().first_child(&org).unwrap();
+            let config = Config::default();
+            let handler = CommonHtmlHandler {
+                citations: Rc::new(Citations::collect(
+                    &org,
+                    &headline,
+                    &rendered_nodes(&org, &headline, false, &config),
+                    Some(&bibliography),
+                    citation_style,
+                )?),
+                ..CommonHtmlHandler::new(&config, Default::default(), "/post")
+            };
+            write_html(&headline, &org, handler)
+        };
+        let source = "* post\nas [cite/t:@sicp] said [cite:see @knuth1984 p. 3; @sicp]\n";
+
+        let html = render_cites(source, CitationStyle::AuthorYear)?;
+        assert!(html.contains(
+            r##"as <span class="citation"><a href="#cite-sicp">Abelson and Sussman (1996)</a></span> said"##
+        ));
+        assert!(html.contains(
+            r##"said <span class="citation">(see <a href="#cite-knuth1984">Knuth 1984</a>, p. 3; <a href="#cite-sicp">Abelson and Sussman 1996</a>)</span>"##
+        ));
+
+        let html = render_cites(source, CitationStyle::Numeric)?;
+        assert!(html.contains(
+            r##"<span class="citation"><a href="#cite-sicp">Abelson and Sussman [1]</a></span>"##
+        ));
+        assert!(html.contains(
+            r##"<span class="citation">[see <a href="#cite-knuth1984">2</a>, p. 3, <a href="#cite-sicp">1</a>]</span>"##
+        ));
+
+        let err = render_cites("* post\n[cite:@missing]\n", CitationStyle::Numeric).unwrap_err();
+        assert_eq!(
+            "citation key '@missing' doesn't exist in the bibliography",
+            err.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn math() -> Result<()> {
+        let html = render("* post\nsee $x^2$ and \\(\\operatorname*{foo}\\) for $5 or $10\n")?;
+        assert!(html.contains("<math><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow></math>"));
+        // math that can't be converted is left as code instead of failing the build
+        assert!(html.contains(r#"<code class="math-error">\operatorname*{foo}</code>"#));
+        assert!(html.contains("for $5 or $10"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn special_blocks() -> Result<()> {
+        let html = render(
+            r#"
+* post
+#+begin_details Summary text
+hidden
+#+end_details
+
+#+begin_warning
+careful
+#+end_warning
+
+#+begin_macro greet name
+hi {{ name }}
+#+end_macro
+"#,
+        )?;
+
+        assert!(html.contains("<details ><summary>Summary text</summary><p >hidden</p></details>"));
+        assert!(html.contains(r#"<div  class="warning" ><p >careful</p></div>"#));
+        assert!(!html.contains("hi {{ name }}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn special_block_templates() -> Result<()> {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "blocks/warning.html",
+            r#"<div class="callout">{{ parameters }}: {{ content | safe }}</div>"#,
+        )?;
+        tera.add_raw_template(
+            "blocks/tip.html",
+            r#"<div class="tip">{{ content | safe }}</div>"#,
+        )?;
+        let config = Config {
+            block_templates: std::sync::Arc::new(tera),
+            ..Default::default()
+        };
+
+        let html = render_with(
+            r#"
+* post
+#+begin_warning Careful
+#+begin_tip
+*bold*
+#+end_tip
+#+end_warning
+"#,
+            &config,
+        )?;
+
+        assert!(html.contains(
+            r#"<div class="callout">Careful: <div class="tip"><p ><b >bold</b></p></div></div>"#
+        ));
+
+        // skipped elements inside of a block don't keep it open
+        let html = render_with(
+            r#"
+* post
+#+begin_tip
+#+begin_src sh
+echo hidden
+#+end_src
+
+#+RESULTS:
+: hidden
+
+#+begin_macro greet name
+hi {{ name }}
+#+end_macro
+#+end_tip
+
+after the block
+"#,
+            &config,
+        )?;
+        assert!(html.ends_with("</pre></div></div><p >after the block</p></section>"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::ops::RangeInclusive;
+
+use crate::helpers::parse_plist;
+
+/// header arguments of a source block, like `:exports results :file main.rs`
+#[derive(Debug, Default, Clone)]
+pub struct HeaderArgs {
+    pairs: Vec<(String, String)>,
+}
+
+impl HeaderArgs {
+    pub fn parse(arguments: &str) -> Self {
+        Self {
+            pairs: parse_plist(arguments),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// the number of the first line, if line numbers were enabled with `:linenos t` or `:linenos 10`
+    pub fn line_numbers(&self) -> Option<usize> {
+        match self.get("linenos")? {
+            "t" | "yes" | "" => Some(1),
+            v => v.parse().ok(),
+        }
+    }
+
+    /// lines marked with `:hl_lines 1,3-5`, counting from the first line of the block
+    pub fn highlighted_lines(&self) -> Vec<RangeInclusive<usize>> {
+        self.get("hl_lines")
+            .map(parse_line_ranges)
+            .unwrap_or_default()
+    }
+
+    pub fn exports(&self) -> Exports {
+        match self.get("exports") {
+            Some("results") => Exports::Results,
+            Some("both") => Exports::Both,
+            Some("none") => Exports::None,
+            _ => Exports::Code,
+        }
+    }
+}
+
+/// what gets exported for a source block, set with the `:exports` header argument
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Exports {
+    #[default]
+    Code,
+    Results,
+    Both,
+    None,
+}
+
+impl Exports {
+    pub fn code(self) -> bool {
+        matches!(self, Exports::Code | Exports::Both)
+    }
+
+    pub fn results(self) -> bool {
+        matches!(self, Exports::Results | Exports::Both)
+    }
+}
+
+/// parses line ranges like "1,3-5 8"
+fn parse_line_ranges(value: &str) -> Vec<RangeInclusive<usize>> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|range| match range.split_once('-') {
+            Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+            None => {
+                let line = range.trim().parse().ok()?;
+                Some(line..=line)
+            }
+        })
+        .collect()
+}
+
+/// splits highlighted html into lines, closing the spans that are open at the end of a line
+/// and reopening them on the next, so every line can be wrapped in its own element
+pub fn split_html_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut rest = html;
+
+    while let Some(i) = rest.find(['<', '\n']) {
+        line.push_str(&rest[..i]);
+        has_text |= i > 0;
+        rest = &rest[i..];
+
+        if rest.starts_with('\n') {
+            line.extend(open.iter().map(|_| "</span>"));
+            lines.push(std::mem::take(&mut line));
+            line.extend(open.iter().copied());
+            has_text = false;
+            rest = &rest[1..];
+            continue;
+        }
+
+        let end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+        let tag = &rest[..end];
+        if tag.starts_with("</") {
+            open.pop();
+        } else if !tag.ends_with("/>") {
+            open.push(tag);
+        }
+        line.push_str(tag);
+        rest = &rest[end..];
+    }
+    line.push_str(rest);
+
+    // don't leave an empty line for the trailing newline
+    if has_text || !rest.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header_args() {
+        let args = HeaderArgs::parse(" :exports both :file main.rs");
+        assert_eq!(Some("main.rs"), args.get("file"));
+        assert_eq!(None, args.get("tangle"));
+        assert_eq!(Exports::Both, args.exports());
+
+        assert_eq!(Exports::Code, HeaderArgs::parse("").exports());
+        assert!(!Exports::Results.code());
+        assert!(Exports::Results.results());
+    }
+
+    #[test]
+    fn parse_lines() {
+        let args = HeaderArgs::parse(":linenos t :hl_lines 1,3-5 8");
+        assert_eq!(Some(1), args.line_numbers());
+        assert_eq!(vec![1..=1, 3..=5, 8..=8], args.highlighted_lines());
+
+        assert_eq!(Some(10), HeaderArgs::parse(":linenos 10").line_numbers());
+        assert_eq!(None, HeaderArgs::parse(":linenos nil").line_numbers());
+        assert_eq!(None, HeaderArgs::parse("").line_numbers());
+    }
+
+    #[test]
+    fn splits_html_lines() {
+        assert_eq!(
+            vec![
+                "<span a>one</span>",
+                "<span a>two <span b>x</span></span>",
+                "<span a></span>",
+                "<span a>three</span>",
+            ],
+            split_html_lines("<span a>one\ntwo <span b>x</span>\n\nthree\n</span>")
+        );
+        assert_eq!(vec!["one", "two"], split_html_lines("one\ntwo\n"));
+    }
+}
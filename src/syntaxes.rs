@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    io::Cursor,
+    sync::{Arc, OnceLock},
+};
+
+use color_eyre::{eyre::Context, Result};
+use orgize::syntect::{
+    highlighting::{Theme, ThemeSet},
+    parsing::{SyntaxDefinition, SyntaxSet},
+};
+use vfs::VfsPath;
+
+/// syntaxes and themes available for highlighting code
+#[derive(Debug)]
+pub struct Syntaxes {
+    pub syntax_set: SyntaxSet,
+    pub themes: BTreeMap<String, Theme>,
+}
+
+static DEFAULT_SYNTAXES: OnceLock<Arc<Syntaxes>> = OnceLock::new();
+
+impl Syntaxes {
+    /// the syntaxes and themes that come bundled with syntect
+    pub fn defaults() -> Arc<Self> {
+        DEFAULT_SYNTAXES
+            .get_or_init(|| {
+                Arc::new(Self {
+                    syntax_set: SyntaxSet::load_defaults_newlines(),
+                    themes: ThemeSet::load_defaults().themes,
+                })
+            })
+            .clone()
+    }
+
+    /// the bundled syntaxes and themes, plus any `.sublime-syntax` and `.tmTheme` files in `folder`
+    ///
+    /// themes are named after their file, without the extension
+    pub fn load(folder: &VfsPath) -> Result<Arc<Self>> {
+        let defaults = Self::defaults();
+
+        let mut builder = defaults.syntax_set.clone().into_builder();
+        let mut themes = defaults.themes.clone();
+
+        let files = folder
+            .walk_dir()
+            .with_context(|| format!("Failed to read syntaxes folder '{}'", folder.as_str()))?;
+        for file in files {
+            let file = file?;
+            let extension = file.extension().unwrap_or_default();
+
+            if extension.eq_ignore_ascii_case("sublime-syntax") {
+                let source = file.read_to_string()?;
+                let syntax = SyntaxDefinition::load_from_str(&source, true, None)
+                    .with_context(|| format!("Failed to load syntax '{}'", file.as_str()))?;
+                builder.add(syntax);
+            } else if extension.eq_ignore_ascii_case("tmTheme") {
+                let source = file.read_to_string()?;
+                let theme = ThemeSet::load_from_reader(&mut Cursor::new(source))
+                    .with_context(|| format!("Failed to load theme '{}'", file.as_str()))?;
+                let name = file.filename();
+                let name = name.strip_suffix(&format!(".{extension}")).unwrap_or(&name);
+                themes.insert(name.to_string(), theme);
+            }
+        }
+
+        Ok(Arc::new(Self {
+            syntax_set: builder.build(),
+            themes,
+        }))
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt::Write;
+
+use orgize::elements::TableRow;
+
+/// collects the rows of an org table while it's being rendered
+///
+/// tables can't be written as they are streamed, since alignment cookies and column groups
+/// are defined in rows that can appear anywhere in the table, so cells are buffered and the
+/// whole table gets written by [`TableBuilder::finish`]
+#[derive(Debug, Default)]
+pub struct TableBuilder {
+    /// already rendered to html
+    caption: Option<String>,
+    attributes: String,
+    rows: Vec<Row>,
+    discarded: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Row {
+    Header(Vec<Cell>),
+    Body(Vec<Cell>),
+    /// a rule between body rows, which starts a new `<tbody>`
+    Rule,
+}
+
+#[derive(Debug, Default)]
+struct Cell {
+    /// raw text, used to detect special rows
+    text: String,
+    html: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    /// parses an alignment cookie like `<r>` or `<l10>`
+    ///
+    /// returns `Some(None)` for width-only cookies like `<10>`
+    fn parse_cookie(text: &str) -> Option<Option<Self>> {
+        let inner = text.strip_prefix('<')?.strip_suffix('>')?;
+        let (align, width) = match inner.chars().next() {
+            Some('l') => (Some(Self::Left), &inner[1..]),
+            Some('c') => (Some(Self::Center), &inner[1..]),
+            Some('r') => (Some(Self::Right), &inner[1..]),
+            _ => (None, inner),
+        };
+
+        if !width.chars().all(|c| c.is_ascii_digit()) || (align.is_none() && width.is_empty()) {
+            return None;
+        }
+
+        Some(align)
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Self::Left => "org-left",
+            Self::Center => "org-center",
+            Self::Right => "org-right",
+        }
+    }
+}
+
+impl Row {
+    fn cells(&self) -> &[Cell] {
+        match self {
+            Row::Header(cells) | Row::Body(cells) => cells,
+            Row::Rule => &[],
+        }
+    }
+
+    /// rows where every cell is empty or an alignment cookie
+    fn is_alignment(&self) -> bool {
+        let cells = self.cells();
+        cells.iter().any(|c| !c.text.trim().is_empty())
+            && cells.iter().all(|c| {
+                let text = c.text.trim();
+                text.is_empty() || Align::parse_cookie(text).is_some()
+            })
+    }
+
+    /// rows starting with `/`, which define column groups
+    fn is_column_groups(&self) -> bool {
+        self.cells()
+            .first()
+            .map(|c| c.text.trim() == "/")
+            .unwrap_or(false)
+    }
+}
+
+impl TableBuilder {
+    pub fn new(caption: Option<String>, attributes: String) -> Self {
+        Self {
+            caption,
+            attributes,
+            ..Default::default()
+        }
+    }
+
+    pub fn start_row(&mut self, row: &TableRow) {
+        match row {
+            TableRow::Header => self.rows.push(Row::Header(Vec::new())),
+            TableRow::Body => self.rows.push(Row::Body(Vec::new())),
+            TableRow::BodyRule => self.rows.push(Row::Rule),
+            // header rows are already marked as such
+            TableRow::HeaderRule => {}
+        }
+    }
+
+    pub fn start_cell(&mut self) {
+        if let Some(Row::Header(cells) | Row::Body(cells)) = self.rows.last_mut() {
+            cells.push(Cell::default());
+        }
+    }
+
+    pub fn push_text(&mut self, text: &str) {
+        if let Some(cell) = self.current_cell() {
+            cell.text.push_str(text);
+        }
+    }
+
+    /// buffer where the html for the contents of the current cell should be written
+    pub fn cell_writer(&mut self) -> &mut Vec<u8> {
+        // content outside of a cell should never happen, so it can just get dropped
+        if self.current_cell().is_none() {
+            self.discarded.clear();
+            return &mut self.discarded;
+        }
+        &mut self.current_cell().unwrap().html
+    }
+
+    fn current_cell(&mut self) -> Option<&mut Cell> {
+        match self.rows.last_mut() {
+            Some(Row::Header(cells) | Row::Body(cells)) => cells.last_mut(),
+            _ => None,
+        }
+    }
+
+    /// renders the collected table as html
+    pub fn finish(mut self) -> String {
+        let mut alignment = Vec::new();
+        let mut groups = None;
+        self.rows.retain(|row| {
+            if row.is_column_groups() {
+                groups = Some(
+                    row.cells()
+                        .iter()
+                        .map(|c| c.text.trim().to_string())
+                        .collect::<Vec<_>>(),
+                );
+                false
+            } else if row.is_alignment() {
+                alignment = row
+                    .cells()
+                    .iter()
+                    .map(|c| Align::parse_cookie(c.text.trim()).flatten())
+                    .collect();
+                false
+            } else {
+                true
+            }
+        });
+        // the rule under a removed cookie row shouldn't produce an empty tbody
+        while let Some(Row::Rule) = self.rows.first() {
+            self.rows.remove(0);
+        }
+
+        let columns = self
+            .rows
+            .iter()
+            .map(|r| r.cells().len())
+            .chain(groups.as_ref().map(Vec::len))
+            .max()
+            .unwrap_or(0);
+        let align = |i: usize| alignment.get(i).copied().flatten();
+
+        let mut out = String::new();
+        let _ = write!(out, "<table {}>", self.attributes);
+        if let Some(caption) = &self.caption {
+            let _ = write!(out, "<caption>{caption}</caption>");
+        }
+
+        if let Some(groups) = groups {
+            // the first column always starts a group, `<` starts one, `>` ends one, and `<>` is a group on its own
+            let mut open = false;
+            for i in 0..columns {
+                let marker = groups.get(i).map(String::as_str).unwrap_or("");
+                if i == 0 || marker == "<" || marker == "<>" {
+                    if open {
+                        out.push_str("</colgroup>");
+                    }
+                    out.push_str("<colgroup>");
+                    open = true;
+                }
+                match align(i) {
+                    Some(a) => {
+                        let _ = write!(out, "<col class=\"{}\" />", a.class());
+                    }
+                    None => out.push_str("<col />"),
+                }
+                if marker == ">" || marker == "<>" {
+                    out.push_str("</colgroup>");
+                    open = false;
+                }
+            }
+            if open {
+                out.push_str("</colgroup>");
+            }
+        }
+
+        let has_header = self.rows.iter().any(|r| matches!(r, Row::Header(_)));
+        if has_header {
+            out.push_str("<thead>");
+        }
+        let mut in_body = false;
+        for row in &self.rows {
+            let tag = match row {
+                Row::Header(_) => "th",
+                Row::Body(_) => {
+                    if !in_body {
+                        if has_header {
+                            out.push_str("</thead>");
+                        }
+                        out.push_str("<tbody>");
+                        in_body = true;
+                    }
+                    "td"
+                }
+                Row::Rule => {
+                    if in_body {
+                        out.push_str("</tbody><tbody>");
+                    }
+                    continue;
+                }
+            };
+
+            out.push_str("<tr>");
+            for (i, cell) in row.cells().iter().enumerate() {
+                match align(i) {
+                    Some(a) => {
+                        let _ = write!(out, "<{tag} class=\"{}\">", a.class());
+                    }
+                    None => {
+                        let _ = write!(out, "<{tag}>");
+                    }
+                }
+                out.push_str(&String::from_utf8_lossy(&cell.html));
+                let _ = write!(out, "</{tag}>");
+            }
+            out.push_str("</tr>");
+        }
+        if in_body {
+            out.push_str("</tbody>");
+        } else if has_header {
+            out.push_str("</thead>");
+        }
+        out.push_str("</table>");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(table: &mut TableBuilder, kind: TableRow, cells: &[&str]) {
+        table.start_row(&kind);
+        for cell in cells {
+            table.start_cell();
+            table.push_text(cell);
+            table.cell_writer().extend_from_slice(cell.as_bytes());
+        }
+    }
+
+    #[test]
+    fn parse_alignment_cookies() {
+        assert_eq!(Some(Some(Align::Left)), Align::parse_cookie("<l>"));
+        assert_eq!(Some(Some(Align::Right)), Align::parse_cookie("<r10>"));
+        assert_eq!(Some(None), Align::parse_cookie("<10>"));
+        assert_eq!(None, Align::parse_cookie("<>"));
+        assert_eq!(None, Align::parse_cookie("<b>"));
+        assert_eq!(None, Align::parse_cookie("r"));
+    }
+
+    #[test]
+    fn renders_header_and_body() {
+        let mut table = TableBuilder::new(Some("a caption".to_string()), String::new());
+        row(&mut table, TableRow::Header, &["a", "b"]);
+        row(&mut table, TableRow::HeaderRule, &[]);
+        row(&mut table, TableRow::Body, &["1", "2"]);
+        row(&mut table, TableRow::BodyRule, &[]);
+        row(&mut table, TableRow::Body, &["3", "4"]);
+
+        assert_eq!(
+            "<table ><caption>a caption</caption>\
+             <thead><tr><th>a</th><th>b</th></tr></thead>\
+             <tbody><tr><td>1</td><td>2</td></tr></tbody>\
+             <tbody><tr><td>3</td><td>4</td></tr></tbody></table>",
+            table.finish()
+        );
+    }
+
+    #[test]
+    fn applies_alignment_and_column_groups() {
+        let mut table = TableBuilder::new(None, String::new());
+        row(&mut table, TableRow::Body, &["/", "<", ">"]);
+        row(&mut table, TableRow::Body, &["", "<c>", "<r>"]);
+        row(&mut table, TableRow::Body, &["1", "2", "3"]);
+
+        assert_eq!(
+            "<table ><colgroup><col /></colgroup>\
+             <colgroup><col class=\"org-center\" /><col class=\"org-right\" /></colgroup>\
+             <tbody><tr><td>1</td><td class=\"org-center\">2</td><td class=\"org-right\">3</td></tr></tbody></table>",
+            table.finish()
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::Write;
+
+use color_eyre::{eyre::bail, Result};
+use orgize::{syntect::parsing::SyntaxSet, Element, Headline, Org};
+use serde_derive::{Deserialize, Serialize};
+use vfs::VfsPath;
+
+use crate::source_block::HeaderArgs;
+
+/// a file generated from the source blocks with a `:tangle` header argument
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TangledFile {
+    pub name: String,
+    pub url: String,
+    pub contents: String,
+}
+
+/// files the generator writes into page folders, which tangled files would overwrite or be overwritten by
+const GENERATED_FILES: &[&str] = &["index.html", "rss.xml"];
+
+/// collects the source blocks under this headline into files, in the order they appear
+///
+/// blocks tangled into the same file are separated by an empty line, like org does.
+/// `:tangle yes` uses the page's slug as the file name, with the extension of the block's language
+pub fn get_tangled_files(
+    org: &Org<'_>,
+    headline: &Headline,
+    page_url: &str,
+    slug: &str,
+    syntax_set: &SyntaxSet,
+) -> Result<Vec<TangledFile>> {
+    let mut files: Vec<TangledFile> = Vec::new();
+
+    for node in headline.headline_node().descendants(org.arena()) {
+        let Element::SourceBlock(block) = &org[node] else {
+            continue;
+        };
+
+        let args = HeaderArgs::parse(&block.arguments);
+        let name = match args.get("tangle") {
+            None | Some("no" | "nil" | "") => continue,
+            Some("yes") => match syntax_set
+                .find_syntax_by_token(&block.language)
+                .and_then(|syntax| syntax.file_extensions.first())
+            {
+                Some(extension) => format!("{slug}.{extension}"),
+                None => bail!(
+                    "can't tell the file extension of '{}' blocks, use `:tangle <file name>` instead of `:tangle yes`",
+                    block.language
+                ),
+            },
+            Some(name) => name.to_string(),
+        };
+        if name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            bail!("tangled file '{name}' must be a path relative to the page");
+        }
+        if name
+            .rsplit('/')
+            .next()
+            .is_some_and(|file| GENERATED_FILES.contains(&file))
+        {
+            bail!("tangled file '{name}' would overwrite a generated page or feed");
+        }
+
+        match files.iter_mut().find(|f| f.name == name) {
+            Some(file) => {
+                file.contents.push('\n');
+                file.contents.push_str(&block.contents);
+            }
+            None => files.push(TangledFile {
+                url: format!("{}/{name}", page_url.trim_end_matches('/')),
+                name,
+                contents: block.contents.to_string(),
+            }),
+        }
+    }
+
+    Ok(files)
+}
+
+/// writes tangled files into the page's output folder
+pub fn write_tangled_files(files: &[TangledFile], out_path: &VfsPath) -> Result<()> {
+    for file in files {
+        let path = out_path.join(&file.name)?;
+        path.parent().create_dir_all()?;
+        path.create_file()?.write_all(file.contents.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntaxes::Syntaxes;
+
+    use super::*;
+
+    fn tangle(source: &str) -> Result<Vec<TangledFile>> {
+        let org = Org::parse(source);
+        get_tangled_files(
+            &org,
+            &org.document().first_child(&org).unwrap(),
+            "http://localhost/post",
+            "post",
+            &Syntaxes::defaults().syntax_set,
+        )
+    }
+
+    #[test]
+    fn tangles_blocks() -> Result<()> {
+        let source = r#"
+* post
+#+begin_src rust :tangle main.rs
+fn main() {}
+#+end_src
+
+#+begin_src rust
+// not tangled
+#+end_src
+** section
+#+begin_src rust :tangle main.rs
+fn other() {}
+#+end_src
+
+#+begin_src toml :tangle no
+#+end_src
+
+#+begin_src python :tangle yes
+print("hi")
+#+end_src
+"#;
+
+        assert_eq!(
+            vec![
+                TangledFile {
+                    name: "main.rs".to_string(),
+                    url: "http://localhost/post/main.rs".to_string(),
+                    contents: "fn main() {}\n\nfn other() {}\n".to_string(),
+                },
+                TangledFile {
+                    name: "post.py".to_string(),
+                    url: "http://localhost/post/post.py".to_string(),
+                    contents: "print(\"hi\")\n".to_string(),
+                }
+            ],
+            tangle(source)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_page() {
+        let source = r#"
+* post
+#+begin_src rust :tangle ../main.rs
+fn main() {}
+#+end_src
+"#;
+
+        assert!(tangle(source).is_err());
+
+        // files the generator writes itself
+        assert!(tangle("* post\n#+begin_src html :tangle index.html\n#+end_src\n").is_err());
+        assert!(tangle("* post\n#+begin_src xml :tangle feed/rss.xml\n#+end_src\n").is_err());
+
+        // `:tangle yes` needs a language with a known extension
+        assert!(tangle("* post\n#+begin_src nonsense :tangle yes\n#+end_src\n").is_err());
+    }
+}
@@ -1,6 +1,8 @@
+use color_eyre::Result;
 use serde::Serialize;
 use std::{borrow::Cow, collections::HashMap};
 use tera::{to_value, Tera, Value};
+use vfs::VfsPath;
 
 use crate::{
     config::Config,
@@ -22,6 +24,25 @@ pub fn make_tera(config: &Config) -> Result<Tera, tera::Error> {
     ))
 }
 
+/// loads the templates for special blocks, from `blocks/{name}.html` in the templates folder
+pub fn make_block_tera(templates_path: &VfsPath) -> Result<Tera> {
+    let mut tera = Tera::default();
+
+    let folder = templates_path.join("blocks")?;
+    if !folder.exists()? {
+        return Ok(tera);
+    }
+
+    for file in folder.read_dir()? {
+        if file.extension().as_deref() == Some("html") {
+            let name = format!("blocks/{}", file.filename());
+            tera.add_raw_template(&name, &file.read_to_string()?)?;
+        }
+    }
+
+    Ok(tera)
+}
+
 pub fn make_get_pages(root: &'_ Page<'_>) -> impl tera::Function {
     let mut map = HashMap::new();
 
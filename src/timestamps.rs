@@ -0,0 +1,96 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDate, NaiveTime,
+};
+use color_eyre::{
+    eyre::{bail, ContextCompat},
+    Result,
+};
+use orgize::{elements::Datetime, export::HtmlEscape};
+
+/// fails if a `strftime` format has unknown specifiers, which chrono would panic on when formatting
+pub fn validate_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        bail!("invalid timestamp format '{format}'");
+    }
+    Ok(())
+}
+
+/// renders a datetime as a `<time>` element, with `date_format` or `time_format` depending on whether it has a time
+pub fn time_html(datetime: &Datetime, date_format: &str, time_format: &str) -> Result<String> {
+    let date = NaiveDate::from_ymd_opt(
+        datetime.year.into(),
+        datetime.month.into(),
+        datetime.day.into(),
+    )
+    .with_context(|| {
+        format!(
+            "invalid date in timestamp: {}-{:02}-{:02}",
+            datetime.year, datetime.month, datetime.day
+        )
+    })?;
+
+    let (machine, human) = match (datetime.hour, datetime.minute) {
+        (Some(hour), Some(minute)) => {
+            let time = NaiveTime::from_hms_opt(hour.into(), minute.into(), 0)
+                .with_context(|| format!("invalid time in timestamp: {hour:02}:{minute:02}"))?;
+            let datetime = date.and_time(time);
+            (
+                datetime.format("%Y-%m-%dT%H:%M").to_string(),
+                datetime.format(time_format).to_string(),
+            )
+        }
+        _ => (
+            date.format("%Y-%m-%d").to_string(),
+            date.format(date_format).to_string(),
+        ),
+    };
+
+    Ok(format!(
+        "<time datetime=\"{machine}\">{}</time>",
+        HtmlEscape(human)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(hour: Option<u8>, minute: Option<u8>) -> Datetime<'static> {
+        Datetime {
+            year: 2023,
+            month: 3,
+            day: 7,
+            dayname: "Tue".into(),
+            hour,
+            minute,
+        }
+    }
+
+    #[test]
+    fn renders_time_elements() -> Result<()> {
+        assert_eq!(
+            r#"<time datetime="2023-03-07">March 7, 2023</time>"#,
+            time_html(&datetime(None, None), "%B %-d, %Y", "%B %-d, %Y %H:%M")?
+        );
+        assert_eq!(
+            r#"<time datetime="2023-03-07T09:05">March 7, 2023 09:05</time>"#,
+            time_html(
+                &datetime(Some(9), Some(5)),
+                "%B %-d, %Y",
+                "%B %-d, %Y %H:%M"
+            )?
+        );
+
+        let invalid = Datetime {
+            month: 13,
+            ..datetime(None, None)
+        };
+        assert!(time_html(&invalid, "%Y", "%Y").is_err());
+
+        assert!(validate_format("%Y-%m-%d").is_ok());
+        assert!(validate_format("%Y-%!").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+/// converts straight quotes to curly ones, `--` and `---` to en and em dashes, and `...` to an ellipsis
+///
+/// `previous` is the character before the text, to tell opening quotes from closing ones.
+/// french also gets guillemets, and non-breaking spaces before `:;!?` instead of normal ones
+pub fn smarten(text: &str, previous: Option<char>, language: Option<&str>) -> String {
+    let french =
+        language.is_some_and(|l| l == "fr" || l.starts_with("fr-") || l.starts_with("fr_"));
+
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(text.len());
+    let mut prev = previous;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        let opening = prev.is_none_or(|p| p.is_whitespace() || "([{‘“«—–/".contains(p));
+
+        let (replacement, len) = match c {
+            '-' if rest.starts_with(&['-', '-', '-']) => ("—", 3),
+            '-' if rest.starts_with(&['-', '-']) => ("–", 2),
+            '.' if rest.starts_with(&['.', '.', '.']) => ("…", 3),
+            '"' if french && opening => ("«\u{a0}", 1),
+            '"' if french => ("\u{a0}»", 1),
+            '"' if opening => ("“", 1),
+            '"' => ("”", 1),
+            '\'' if opening => ("‘", 1),
+            // apostrophes are closing quotes too
+            '\'' => ("’", 1),
+            ':' | ';' | '!' | '?' | '»' if french && out.ends_with(' ') => {
+                out.pop();
+                out.push(if c == ':' || c == '»' {
+                    '\u{a0}'
+                } else {
+                    '\u{202f}'
+                });
+                ("", 0)
+            }
+            ' ' if french && prev == Some('«') => ("\u{a0}", 1),
+            _ => ("", 0),
+        };
+
+        if len == 0 {
+            out.push(c);
+            i += 1;
+        } else {
+            out.push_str(replacement);
+            i += len;
+        }
+        prev = out.chars().last();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smartens_text() {
+        assert_eq!(
+            "“it’s ‘fine’” — she said… 1–2",
+            smarten(r#""it's 'fine'" --- she said... 1--2"#, None, None)
+        );
+        // a quote right after other text closes, even if the text came before some markup
+        assert_eq!("” and", smarten(r#"" and"#, Some('d'), None));
+        assert_eq!("“", smarten(r#"""#, Some(' '), None));
+    }
+
+    #[test]
+    fn french_spaces() {
+        assert_eq!(
+            "«\u{a0}oui\u{a0}» et\u{a0}: quoi\u{202f}? «\u{a0}non\u{a0}»",
+            smarten(r#""oui" et : quoi ? « non »"#, None, Some("fr"))
+        );
+        // missing spaces aren't added, so times and links stay intact
+        assert_eq!("10:30!", smarten("10:30!", None, Some("fr-FR")));
+        assert_eq!("et : quoi ?", smarten("et : quoi ?", None, Some("en")));
+    }
+}